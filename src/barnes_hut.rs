@@ -0,0 +1,280 @@
+use std::process::exit;
+
+use crate::body::Particles;
+use crate::gravity::direct;
+
+const NONE: usize = usize::MAX;
+// cells are not split below this depth, coincident bodies share a leaf
+const MAX_DEPTH: usize = 64;
+
+struct Node {
+    center: [f64; 3],
+    half: f64,
+    mass: f64,
+    // center of mass
+    com: [f64; 3],
+    // first child, the 8 children are stored consecutively
+    child: usize,
+    // first body of a leaf, the rest are linked through Octree::next
+    body: usize
+}
+
+impl Node {
+    fn new(center: [f64; 3], half: f64) -> Node {
+        Node {
+            center : center,
+            half : half,
+            mass : 0.0,
+            com : [0.0, 0.0, 0.0],
+            child : NONE,
+            body : NONE
+        }
+    }
+
+    fn octant(&self, r: &[f64; 3]) -> usize {
+        let mut o = 0;
+        for (k, (x, c)) in r.iter().zip(self.center.iter()).enumerate() {
+            if x >= c {
+                o |= 1 << k;
+            }
+        }
+        o
+    }
+}
+
+// Barnes-Hut octree with monopole cells
+pub struct Octree {
+    nodes: Vec<Node>,
+    next: Vec<usize>
+}
+
+impl Octree {
+    pub fn new(p: &Particles) -> Octree {
+        let mut lo = [f64::MAX, f64::MAX, f64::MAX];
+        let mut hi = [f64::MIN, f64::MIN, f64::MIN];
+        for i in 0..p.len() {
+            let r = p.r(i);
+            for k in 0..3 {
+                lo[k] = lo[k].min(r[k]);
+                hi[k] = hi[k].max(r[k]);
+            }
+        }
+        let mut center = [0.0, 0.0, 0.0];
+        let mut half: f64 = 0.0;
+        for k in 0..3 {
+            center[k] = 0.5 * (lo[k] + hi[k]);
+            half = half.max(0.5 * (hi[k] - lo[k]));
+        }
+        // keep bodies on the boundary strictly inside
+        half = half * 1.0001 + 1e-12;
+
+        let mut tree = Octree {
+            nodes : Vec::with_capacity(2 * p.len() + 1),
+            next : vec![NONE; p.len()]
+        };
+        tree.nodes.push(Node::new(center, half));
+        for i in 0..p.len() {
+            if p.r(i).iter().all(|x| x.is_finite()) {
+                tree.insert(p, i);
+            }
+        }
+        tree.summarize(p, 0);
+        tree
+    }
+
+    fn insert(&mut self, p: &Particles, i: usize) {
+        let r = &p.r(i);
+        let mut node = 0;
+        let mut depth = 0;
+        loop {
+            if self.nodes[node].child != NONE {
+                node = self.nodes[node].child + self.nodes[node].octant(r);
+                depth += 1;
+                continue;
+            }
+            if self.nodes[node].body == NONE {
+                self.nodes[node].body = i;
+                return;
+            }
+            if depth >= MAX_DEPTH {
+                self.next[i] = self.nodes[node].body;
+                self.nodes[node].body = i;
+                return;
+            }
+            // split the leaf and push its bodies one level down
+            self.split(node);
+            let mut j = self.nodes[node].body;
+            self.nodes[node].body = NONE;
+            while j != NONE {
+                let next = self.next[j];
+                let c = self.nodes[node].child + self.nodes[node].octant(&p.r(j));
+                self.next[j] = self.nodes[c].body;
+                self.nodes[c].body = j;
+                j = next;
+            }
+        }
+    }
+
+    fn split(&mut self, node: usize) {
+        let first = self.nodes.len();
+        let half = 0.5 * self.nodes[node].half;
+        let center = self.nodes[node].center;
+        for o in 0..8 {
+            let mut c = center;
+            for (k, x) in c.iter_mut().enumerate() {
+                *x += if o & (1 << k) != 0 { half } else { -half };
+            }
+            self.nodes.push(Node::new(c, half));
+        }
+        self.nodes[node].child = first;
+    }
+
+    // masses and centers of mass, bottom up
+    fn summarize(&mut self, p: &Particles, node: usize) {
+        let mut mass = 0.0;
+        let mut com = [0.0, 0.0, 0.0];
+        if self.nodes[node].child != NONE {
+            let first = self.nodes[node].child;
+            for c in first..first + 8 {
+                self.summarize(p, c);
+                let child = &self.nodes[c];
+                mass += child.mass;
+                for (x, y) in com.iter_mut().zip(child.com.iter()) {
+                    *x += child.mass * y;
+                }
+            }
+        } else {
+            let mut j = self.nodes[node].body;
+            while j != NONE {
+                let r = p.r(j);
+                mass += p.m[j];
+                for (x, y) in com.iter_mut().zip(r.iter()) {
+                    *x += p.m[j] * y;
+                }
+                j = self.next[j];
+            }
+        }
+        if mass > 0.0 {
+            for x in com.iter_mut() {
+                *x /= mass;
+            }
+        } else {
+            com = self.nodes[node].center;
+        }
+        self.nodes[node].mass = mass;
+        self.nodes[node].com = com;
+    }
+
+    pub fn accelerations(&self, p: &Particles, g: f64, theta: f64, eps2: f64) -> Vec<[f64; 3]> {
+        let mut acc = vec![[0.0, 0.0, 0.0]; p.len()];
+        let mut stack = Vec::new();
+        for (i, a) in acc.iter_mut().enumerate() {
+            if p.fixed[i] { continue; }
+            let ri = p.r(i);
+
+            stack.clear();
+            stack.push(0);
+            while let Some(node) = stack.pop() {
+                let nd = &self.nodes[node];
+                if nd.mass == 0.0 { continue; }
+
+                if nd.child == NONE {
+                    let mut j = nd.body;
+                    while j != NONE {
+                        if j != i {
+                            add_pair(a, &ri, &p.r(j), g * p.m[j], eps2);
+                        }
+                        j = self.next[j];
+                    }
+                    continue;
+                }
+
+                let mut d2 = 0.0;
+                for (x, y) in nd.com.iter().zip(ri.iter()) {
+                    d2 += (x - y) * (x - y);
+                }
+                let s = 2.0 * nd.half;
+                // a body inside the cell always opens it
+                let inside = (0..3).all(|k| f64::abs(ri[k] - nd.center[k]) <= nd.half);
+                if !inside && s * s < theta * theta * d2 {
+                    add_pair(a, &ri, &nd.com, g * nd.mass, eps2);
+                } else {
+                    for c in nd.child..nd.child + 8 {
+                        stack.push(c);
+                    }
+                }
+            }
+        }
+        acc
+    }
+}
+
+fn add_pair(a: &mut [f64; 3], r1: &[f64; 3], r2: &[f64; 3], gm: f64, eps2: f64) {
+    let mut r = eps2;
+    for k in 0..3 {
+        r += (r1[k] - r2[k]) * (r1[k] - r2[k]);
+    }
+    r = f64::sqrt(r);
+    for k in 0..3 {
+        a[k] += gm * (r2[k] - r1[k]) / r / r / r;
+    }
+}
+
+// xorshift, enough for test data
+fn random(state: &mut u64) -> f64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    (*state >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// max and rms deviation from the direct sum relative to the rms acceleration
+fn compare(p: &Particles, theta: f64) -> (f64, f64) {
+    let mut q = p.clone();
+    direct(&mut q, 1.0, 0.0);
+    let exact: Vec<[f64; 3]> = (0..q.len()).map(|i| q.a(i)).collect();
+    let tree = Octree::new(p);
+    let approx = tree.accelerations(p, 1.0, theta, 0.0);
+    let mut max_err: f64 = 0.0;
+    let mut sum = 0.0;
+    let mut norm = 0.0;
+    for (a, b) in exact.iter().zip(&approx) {
+        let mut d = 0.0;
+        for k in 0..3 {
+            d += (a[k] - b[k]) * (a[k] - b[k]);
+            norm += a[k] * a[k];
+        }
+        max_err = max_err.max(d);
+        sum += d;
+    }
+    (f64::sqrt(max_err / norm * p.len() as f64), f64::sqrt(sum / norm))
+}
+
+// uniform sphere of random masses plus a far body
+pub fn run_test() {
+    let mut state = 88172645463325252u64;
+    let mut p = Particles::new();
+    while p.len() < 2000 {
+        let r = [2.0 * random(&mut state) - 1.0, 2.0 * random(&mut state) - 1.0, 2.0 * random(&mut state) - 1.0];
+        if r[0] * r[0] + r[1] * r[1] + r[2] * r[2] > 1.0 { continue; }
+        p.push(r, [0.0, 0.0, 0.0], random(&mut state));
+    }
+    p.push([100.0, 0.0, 0.0], [0.0, 0.0, 0.0], 1.0);
+
+    let (max0, rms0) = compare(&p, 0.0);
+    let (max1, rms1) = compare(&p, 0.3);
+    let (max2, rms2) = compare(&p, 0.7);
+    println!("theta 0: {max0} {rms0}");
+    println!("theta 0.3: {max1} {rms1}");
+    println!("theta 0.7: {max2} {rms2}");
+    if max0 > 1e-10 {
+        println!("Error1"); exit(1);
+    }
+    if rms1 > 2e-3 || max1 > 1e-2 {
+        println!("Error2"); exit(1);
+    }
+    if rms2 > 2e-2 || max2 > 1e-1 || rms2 < rms1 {
+        println!("Error3"); exit(1);
+    }
+    println!("Ok");
+}
@@ -0,0 +1,94 @@
+use std::time::Instant;
+
+use crate::body::System;
+use crate::gravity::direct;
+
+// the array-of-structs layout the force loop used to walk,
+// kept only as the baseline for the benchmark
+struct AosBody {
+    #[allow(dead_code)]
+    name: String,
+    #[allow(dead_code)]
+    color: String,
+    r: [f64; 3],
+    #[allow(dead_code)]
+    v: [f64; 3],
+    a: [f64; 3],
+    #[allow(dead_code)]
+    a_next: [f64; 3],
+    m: f64,
+    fixed: bool
+}
+
+fn aos_direct(bodies: &mut [AosBody], g: f64) {
+    let n = bodies.len();
+    for i in 0..n {
+        let mut a = [0.0, 0.0, 0.0];
+        if !bodies[i].fixed {
+            for j in 0..n {
+                if i == j { continue; }
+                let mut r2 = 0.0;
+                let mut d = [0.0, 0.0, 0.0];
+                for ((dk, rj), ri) in d.iter_mut().zip(bodies[j].r).zip(bodies[i].r) {
+                    *dk = rj - ri;
+                    r2 += *dk * *dk;
+                }
+                let f = g * bodies[j].m / (r2 * r2.sqrt());
+                for (ak, dk) in a.iter_mut().zip(d) {
+                    *ak += f * dk;
+                }
+            }
+        }
+        bodies[i].a = a;
+    }
+}
+
+// seconds per call, repeated until at least min_time has passed
+fn time<F: FnMut()>(mut f: F, min_time: f64) -> f64 {
+    f();
+    let mut calls = 0;
+    let start = Instant::now();
+    loop {
+        f();
+        calls += 1;
+        let elapsed = start.elapsed().as_secs_f64();
+        if elapsed >= min_time {
+            return elapsed / calls as f64;
+        }
+    }
+}
+
+// times one direct force evaluation in both layouts
+pub fn run(data: &System) {
+    let p = &data.particles;
+    let mut aos: Vec<AosBody> = (0..p.len()).map(|i| AosBody {
+        name : data.bodies[i].name.clone(),
+        color : data.bodies[i].color.clone(),
+        r : p.r(i),
+        v : p.v(i),
+        a : [0.0, 0.0, 0.0],
+        a_next : [0.0, 0.0, 0.0],
+        m : p.m[i],
+        fixed : p.fixed[i]
+    }).collect();
+    let mut soa = p.clone();
+
+    let t_aos = time(|| aos_direct(&mut aos, data.g), 1.0);
+    let t_soa = time(|| direct(&mut soa, data.g, 0.0), 1.0);
+
+    let mut max_err: f64 = 0.0;
+    let mut max_a: f64 = 0.0;
+    for (i, b) in aos.iter().enumerate() {
+        let a = soa.a(i);
+        for (x, y) in a.iter().zip(b.a) {
+            max_err = max_err.max((x - y).abs());
+            max_a = max_a.max(y.abs());
+        }
+    }
+
+    println!("bodies {}", p.len());
+    println!("aos {:.3} ms", t_aos * 1e3);
+    println!("soa {:.3} ms", t_soa * 1e3);
+    println!("speedup {:.2}", t_aos / t_soa);
+    println!("max relative difference {:e}", max_err / max_a);
+}
@@ -0,0 +1,282 @@
+use std::env;
+use std::process::exit;
+
+use solar_rs::System;
+use solar_rs::integrator::{by_name, METHODS};
+use solar_rs::load::{self, load, to_text, Settings};
+use solar_rs::scenario::{self, Scenario};
+use solar_rs::generate;
+use solar_rs::kepler::run_test;
+use solar_rs::gravity;
+use solar_rs::collision;
+use solar_rs::constraint;
+use solar_rs::field;
+use solar_rs::relativity;
+use solar_rs::nongrav;
+use solar_rs::diagnostics;
+use solar_rs::elements;
+use solar_rs::units;
+use solar_rs::output::{self, Columns, format_by_name};
+use solar_rs::barnes_hut;
+use solar_rs::bench;
+#[cfg(feature = "parallel")]
+use solar_rs::parallel;
+use solar_rs::solve;
+
+fn usage(cmd: &mut String) {
+    let names: Vec<&str> = METHODS.iter().map(|m| m.0).collect();
+    eprintln!("{cmd} --input file.txt|file.toml|file.json [--method {}] [--dt 0.001] [--T 10] [--rtol 1e-9] [--atol 1e-12] [--theta 0.5] [--threads 4] [--softening 0.01] [--collisions off|merge|bounce] [--restitution 0.5] [--diagnostics] [--elements] [--primary name] [--format text|binary] [--convert out.toml|out.json|out.txt] [--test] [--bench]", names.join("|"));
+    eprintln!("  --rtol and --atol set the error tolerance of dopri5; ias15 uses --rtol, at least 1e-10, and ignores --atol");
+    generate_usage(cmd);
+}
+
+// the format follows the extension: .toml, .json or the legacy text
+fn write(file_name: &str, data: &System, settings: &Settings) {
+    let text = if file_name.ends_with(".toml") {
+        Scenario::from_system(data, settings).to_toml()
+    } else if file_name.ends_with(".json") {
+        Scenario::from_system(data, settings).to_json()
+    } else {
+        to_text(data)
+    };
+    if let Err(e) = std::fs::write(file_name, text) {
+        eprintln!("{file_name}: {e}");
+        exit(1);
+    }
+}
+
+fn generate_usage(cmd: &mut String) {
+    let names: Vec<&str> = generate::KINDS.iter().map(|k| k.0).collect();
+    eprintln!("{cmd} generate {} --output file.txt|file.toml|file.json [--n 1000] [--seed 1] [--G 1] [--central 1] [--rmin 1] [--rmax 2] [--scale 0.67] [--mmin 1e-6] [--mmax 1e-6]", names.join("|"));
+}
+
+// nbody generate kind --output file ...
+fn generate_command(argv: &mut [String]) {
+    let argc = argv.len();
+    let kind = match argv.get(2).and_then(|name| generate::kind_by_name(name)) {
+        Some(kind) => kind,
+        None => { generate_usage(&mut argv[0]); return; }
+    };
+    let mut params = generate::Params::new();
+    let mut output = String::new();
+    let mut i = 3;
+    while i < argc {
+        if i < argc-1 && argv[i] == "--output" {
+            i += 1;
+            output = argv[i].clone();
+        } else if i < argc-1 && argv[i] == "--n" {
+            i += 1;
+            params.n = argv[i].parse::<usize>().unwrap();
+        } else if i < argc-1 && argv[i] == "--seed" {
+            i += 1;
+            params.seed = argv[i].parse::<u64>().unwrap();
+        } else if i < argc-1 && argv[i] == "--G" {
+            i += 1;
+            params.g = argv[i].parse::<f64>().unwrap();
+        } else if i < argc-1 && argv[i] == "--central" {
+            i += 1;
+            params.central = argv[i].parse::<f64>().unwrap();
+        } else if i < argc-1 && argv[i] == "--rmin" {
+            i += 1;
+            params.rmin = argv[i].parse::<f64>().unwrap();
+        } else if i < argc-1 && argv[i] == "--rmax" {
+            i += 1;
+            params.rmax = argv[i].parse::<f64>().unwrap();
+        } else if i < argc-1 && argv[i] == "--scale" {
+            i += 1;
+            params.scale = Some(argv[i].parse::<f64>().unwrap());
+        } else if i < argc-1 && argv[i] == "--mmin" {
+            i += 1;
+            params.mmin = argv[i].parse::<f64>().unwrap();
+        } else if i < argc-1 && argv[i] == "--mmax" {
+            i += 1;
+            params.mmax = argv[i].parse::<f64>().unwrap();
+        } else {
+            generate_usage(&mut argv[0]); return;
+        }
+        i += 1;
+    }
+    if output.is_empty() {
+        generate_usage(&mut argv[0]); return;
+    }
+    let data = match generate::generate(kind, &params) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("{e}");
+            exit(1);
+        }
+    };
+    write(&output, &data, &Settings::default());
+}
+
+fn main() {
+    let mut argv: Vec<String> = env::args().collect();
+    if argv.len() > 1 && argv[1] == "generate" {
+        generate_command(&mut argv);
+        return;
+    }
+    let mut file_name = String::new();
+    let argc = argv.len();
+    let mut i = 1;
+    // None when not given, then the scenario file or the default decides
+    let mut dt = None;
+    let mut max_time = None;
+    let mut rtol = 1e-9;
+    let mut atol = 1e-12;
+    let mut theta = 0.0;
+    let mut threads = 1;
+    let mut softening = None;
+    let mut collisions = String::from("off");
+    let mut restitution = 0.5;
+    let mut method_name = None;
+    let mut convert = String::new();
+    let mut columns = Columns::default();
+    let mut test_mode = false;
+    let mut bench_mode = false;
+
+    while i < argc {
+        if i < argc-1 && argv[i] == "--input" {
+            i += 1;
+            file_name = argv[i].clone();
+        } else if i < argc-1 && argv[i] == "--method" {
+            i += 1;
+            method_name = Some(argv[i].clone());
+        } else if i < argc-1 && argv[i] == "--dt" {
+            i += 1;
+            dt = Some(argv[i].parse::<f64>().unwrap());
+        } else if i < argc-1 && argv[i] == "--T" {
+            i += 1;
+            max_time = Some(argv[i].parse::<f64>().unwrap());
+        } else if i < argc-1 && argv[i] == "--rtol" {
+            i += 1;
+            rtol = argv[i].parse::<f64>().unwrap();
+        } else if i < argc-1 && argv[i] == "--atol" {
+            i += 1;
+            atol = argv[i].parse::<f64>().unwrap();
+        } else if i < argc-1 && argv[i] == "--theta" {
+            i += 1;
+            theta = argv[i].parse::<f64>().unwrap();
+        } else if i < argc-1 && argv[i] == "--threads" {
+            i += 1;
+            threads = argv[i].parse::<usize>().unwrap();
+        } else if i < argc-1 && argv[i] == "--softening" {
+            i += 1;
+            softening = Some(argv[i].parse::<f64>().unwrap());
+        } else if i < argc-1 && argv[i] == "--collisions" {
+            i += 1;
+            collisions = argv[i].clone();
+        } else if i < argc-1 && argv[i] == "--restitution" {
+            i += 1;
+            restitution = argv[i].parse::<f64>().unwrap();
+        } else if argv[i] == "--diagnostics" {
+            columns.diagnostics = true;
+        } else if argv[i] == "--elements" {
+            columns.elements = true;
+        } else if i < argc-1 && argv[i] == "--primary" {
+            i += 1;
+            columns.primary = Some(argv[i].clone());
+        } else if i < argc-1 && argv[i] == "--format" {
+            i += 1;
+            columns.format = match format_by_name(&argv[i]) {
+                Some(format) => format,
+                None => { usage(&mut argv[0]); return; }
+            };
+        } else if i < argc-1 && argv[i] == "--convert" {
+            i += 1;
+            convert = argv[i].clone();
+        } else if argv[i] == "--test" {
+            test_mode = true;
+        } else if argv[i] == "--bench" {
+            bench_mode = true;
+        } else {
+            usage(&mut argv[0]); return;
+        }
+        i += 1;
+    }
+
+    if test_mode {
+        let mut method = match by_name(method_name.as_deref().unwrap_or("verlet")) {
+            Some(method) => method,
+            None => { usage(&mut argv[0]); return; }
+        };
+        method.set_tolerance(rtol, atol);
+        run_test(method.as_mut());
+        field::run_test(method.as_mut());
+        gravity::run_test();
+        collision::run_test();
+        constraint::run_test();
+        relativity::run_test();
+        nongrav::run_test(method.as_mut());
+        diagnostics::run_test(method.as_mut());
+        elements::run_test();
+        units::run_test();
+        output::run_test();
+        load::run_test();
+        scenario::run_test();
+        generate::run_test();
+        barnes_hut::run_test();
+        #[cfg(feature = "parallel")]
+        parallel::run_test();
+        return;
+    }
+
+    if file_name.is_empty() {
+        usage(&mut argv[0]); return;
+    }
+
+    let mut data = System::new(0.0);
+    data.forces.theta = theta;
+    data.forces.threads = threads;
+    data.collisions.mode = match collision::mode_by_name(&collisions) {
+        Some(mode) => mode,
+        None => { usage(&mut argv[0]); return; }
+    };
+    data.collisions.restitution = restitution;
+    #[cfg(feature = "parallel")]
+    parallel::set_threads(threads);
+    #[cfg(not(feature = "parallel"))]
+    if threads > 1 {
+        eprintln!("built without the parallel feature, --threads is ignored");
+    }
+
+    let settings = match load(&mut data, &file_name) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{e}");
+            exit(1);
+        }
+    };
+    // the command line overrides the value from the file
+    if let Some(eps) = softening {
+        data.forces.softening = eps;
+    }
+    let settings = Settings {
+        dt : dt.or(settings.dt),
+        max_time : max_time.or(settings.max_time),
+        method : method_name.or(settings.method)
+    };
+
+    if !convert.is_empty() {
+        write(&convert, &data, &settings);
+        return;
+    }
+
+    data.dt = settings.dt.unwrap_or(0.0001);
+    let max_time = settings.max_time.unwrap_or(10.0);
+    let mut method = match by_name(settings.method.as_deref().unwrap_or("verlet")) {
+        Some(method) => method,
+        None => { usage(&mut argv[0]); return; }
+    };
+    method.set_tolerance(rtol, atol);
+    if let Some(name) = &columns.primary {
+        if !data.bodies.iter().any(|b| &b.name == name) {
+            eprintln!("no body named {name}");
+            return;
+        }
+    }
+    if bench_mode {
+        bench::run(&data);
+        return;
+    }
+    solve(&mut data, max_time, method.as_mut(), &columns);
+}
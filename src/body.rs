@@ -0,0 +1,165 @@
+use crate::gravity::Forces;
+use crate::collision::Collisions;
+use crate::field::Field;
+use crate::units::Units;
+
+// per-body attributes that the force loop does not need
+#[derive(Clone)]
+pub struct Body {
+    pub name: String,
+    pub color: String,
+    pub rad: f64
+}
+
+impl Body {
+    pub fn new(name: &str) -> Body {
+        Body {
+            name : String::from(name),
+            color : String::from("000000"),
+            rad : 1.0
+        }
+    }
+}
+
+// structure of arrays: one vector per coordinate, so the force loop
+// streams through contiguous f64 slices
+#[derive(Clone, Default)]
+pub struct Particles {
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    pub z: Vec<f64>,
+    pub vx: Vec<f64>,
+    pub vy: Vec<f64>,
+    pub vz: Vec<f64>,
+    pub ax: Vec<f64>,
+    pub ay: Vec<f64>,
+    pub az: Vec<f64>,
+    pub m: Vec<f64>,
+    // physical radius for collisions, 0 never collides
+    pub radius: Vec<f64>,
+    pub fixed: Vec<bool>
+}
+
+impl Particles {
+    pub fn new() -> Particles {
+        Particles::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.m.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.m.is_empty()
+    }
+
+    pub fn push(&mut self, r: [f64; 3], v: [f64; 3], m: f64) {
+        self.x.push(r[0]);
+        self.y.push(r[1]);
+        self.z.push(r[2]);
+        self.vx.push(v[0]);
+        self.vy.push(v[1]);
+        self.vz.push(v[2]);
+        self.ax.push(0.0);
+        self.ay.push(0.0);
+        self.az.push(0.0);
+        self.m.push(m);
+        self.radius.push(0.0);
+        self.fixed.push(false);
+    }
+
+    pub fn remove(&mut self, i: usize) {
+        for c in [&mut self.x, &mut self.y, &mut self.z,
+                  &mut self.vx, &mut self.vy, &mut self.vz,
+                  &mut self.ax, &mut self.ay, &mut self.az,
+                  &mut self.m, &mut self.radius] {
+            c.remove(i);
+        }
+        self.fixed.remove(i);
+    }
+
+    pub fn r(&self, i: usize) -> [f64; 3] {
+        [self.x[i], self.y[i], self.z[i]]
+    }
+
+    pub fn v(&self, i: usize) -> [f64; 3] {
+        [self.vx[i], self.vy[i], self.vz[i]]
+    }
+
+    pub fn a(&self, i: usize) -> [f64; 3] {
+        [self.ax[i], self.ay[i], self.az[i]]
+    }
+
+    pub fn set_r(&mut self, i: usize, r: [f64; 3]) {
+        self.x[i] = r[0];
+        self.y[i] = r[1];
+        self.z[i] = r[2];
+    }
+
+    pub fn set_v(&mut self, i: usize, v: [f64; 3]) {
+        self.vx[i] = v[0];
+        self.vy[i] = v[1];
+        self.vz[i] = v[2];
+    }
+
+    pub fn set_a(&mut self, i: usize, a: [f64; 3]) {
+        self.ax[i] = a[0];
+        self.ay[i] = a[1];
+        self.az[i] = a[2];
+    }
+}
+
+pub struct System {
+    // bodies[i] describes particle i
+    pub bodies: Vec<Body>,
+    pub particles: Particles,
+    pub g: f64,
+    pub dt: f64,
+    pub forces: Forces,
+    pub collisions: Collisions,
+    // physical scale of the internal units, None when nondimensional
+    pub units: Option<Units>
+}
+
+impl System {
+    pub fn new(dt: f64) -> System {
+        System {
+            bodies : Vec::new(),
+            particles : Particles::new(),
+            g : 1.0,
+            dt : dt,
+            forces : Forces::new(),
+            collisions : Collisions::new(),
+            units : None
+        }
+    }
+
+    pub fn add(&mut self, body: Body, r: [f64; 3], v: [f64; 3], m: f64) {
+        self.bodies.push(body);
+        self.particles.push(r, v, m);
+    }
+
+    pub fn remove(&mut self, i: usize) {
+        self.bodies.remove(i);
+        self.particles.remove(i);
+        let constraints = &mut self.forces.constraints;
+        constraints.retain(|c| c.body != i);
+        for c in constraints.iter_mut() {
+            if c.body > i {
+                c.body -= 1;
+            }
+        }
+        // the field of a removed body goes with it
+        let fields = &mut self.forces.fields;
+        fields.retain(|f| !matches!(f, Field::J2 { body, .. } if *body == i));
+        for f in fields.iter_mut() {
+            if let Field::J2 { body, .. } = f {
+                if *body > i {
+                    *body -= 1;
+                }
+            }
+        }
+        let nongrav = &mut self.forces.nongrav;
+        *nongrav = nongrav.iter().filter_map(|f| f.without(i)).collect();
+    }
+}
@@ -0,0 +1,241 @@
+use std::process::exit;
+
+use crate::body::{Body, System};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Off,
+    // overlapping bodies become one, mass and momentum are conserved
+    Merge,
+    // approaching overlapping bodies exchange an impulse along the line of centers
+    Bounce
+}
+
+// names used by --collisions, in the order shown in the GUI
+pub const MODES: &[(&str, &str)] = &[
+    ("off", "Off"),
+    ("merge", "Merge"),
+    ("bounce", "Bounce"),
+];
+
+pub fn mode_by_name(name: &str) -> Option<Mode> {
+    match name {
+        "off" => Some(Mode::Off),
+        "merge" => Some(Mode::Merge),
+        "bounce" => Some(Mode::Bounce),
+        _ => None
+    }
+}
+
+#[derive(Clone)]
+pub struct Collisions {
+    pub mode: Mode,
+    // ratio of the normal relative speed after and before a bounce,
+    // 1 is elastic, 0 removes the normal component
+    pub restitution: f64
+}
+
+impl Collisions {
+    pub fn new() -> Collisions {
+        Collisions {
+            mode : Mode::Off,
+            restitution : 0.5
+        }
+    }
+}
+
+impl Default for Collisions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// indices refer to the bodies at the moment of the event, after a merge
+// body j is gone and the bodies above it shift down by one
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Event {
+    // j was merged into i, i < j
+    Merge(usize, usize),
+    Bounce(usize, usize)
+}
+
+fn overlap(data: &System, i: usize, j: usize) -> bool {
+    let p = &data.particles;
+    let (ri, rj) = (p.r(i), p.r(j));
+    let mut d2 = 0.0;
+    for k in 0..3 {
+        d2 += (rj[k] - ri[k]) * (rj[k] - ri[k]);
+    }
+    let s = p.radius[i] + p.radius[j];
+    d2 < s * s
+}
+
+// j into i: center of mass position and velocity, volumes add up;
+// a fixed body stays where it is and absorbs the other
+fn merge(data: &mut System, i: usize, j: usize) {
+    let p = &mut data.particles;
+    let (mi, mj) = (p.m[i], p.m[j]);
+    let m = mi + mj;
+    let (ri, rj) = (p.r(i), p.r(j));
+    let (vi, vj) = (p.v(i), p.v(j));
+    let mut r = [0.0, 0.0, 0.0];
+    let mut v = [0.0, 0.0, 0.0];
+    for k in 0..3 {
+        r[k] = (mi * ri[k] + mj * rj[k]) / m;
+        v[k] = (mi * vi[k] + mj * vj[k]) / m;
+    }
+    if p.fixed[i] {
+        (r, v) = (ri, vi);
+    } else if p.fixed[j] {
+        (r, v) = (rj, vj);
+        p.fixed[i] = true;
+    }
+    p.set_r(i, r);
+    p.set_v(i, v);
+    p.m[i] = m;
+    p.radius[i] = f64::cbrt(p.radius[i].powi(3) + p.radius[j].powi(3));
+    data.remove(j);
+}
+
+// returns false when the bodies already separate
+fn bounce(data: &mut System, i: usize, j: usize, restitution: f64) -> bool {
+    let p = &mut data.particles;
+    let (ri, rj) = (p.r(i), p.r(j));
+    let (mut vi, mut vj) = (p.v(i), p.v(j));
+    let mut n = [0.0, 0.0, 0.0];
+    let mut d = 0.0;
+    for k in 0..3 {
+        n[k] = rj[k] - ri[k];
+        d += n[k] * n[k];
+    }
+    d = f64::sqrt(d);
+    if d == 0.0 {
+        return false;
+    }
+    let mut vn = 0.0;
+    for k in 0..3 {
+        n[k] /= d;
+        vn += (vj[k] - vi[k]) * n[k];
+    }
+    // fixed bodies have infinite mass
+    let wi = if p.fixed[i] { 0.0 } else { 1.0 / p.m[i] };
+    let wj = if p.fixed[j] { 0.0 } else { 1.0 / p.m[j] };
+    if vn >= 0.0 || wi + wj == 0.0 {
+        return false;
+    }
+    let impulse = -(1.0 + restitution) * vn / (wi + wj);
+    for k in 0..3 {
+        vi[k] -= impulse * wi * n[k];
+        vj[k] += impulse * wj * n[k];
+    }
+    p.set_v(i, vi);
+    p.set_v(j, vj);
+    true
+}
+
+// checks every pair for overlap and resolves it, O(N^2)
+pub fn collide(data: &mut System) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mode = data.collisions.mode;
+    if mode == Mode::Off {
+        return events;
+    }
+
+    // a merged body may reach ones already checked, so the scan restarts
+    'scan: loop {
+        let n = data.particles.len();
+        for i in 0..n {
+            for j in i + 1..n {
+                if !overlap(data, i, j) {
+                    continue;
+                }
+                if mode == Mode::Merge {
+                    merge(data, i, j);
+                    events.push(Event::Merge(i, j));
+                    continue 'scan;
+                }
+                if bounce(data, i, j, data.collisions.restitution) {
+                    events.push(Event::Bounce(i, j));
+                }
+            }
+        }
+        break;
+    }
+    events
+}
+
+fn two_bodies(mode: Mode, restitution: f64) -> System {
+    let mut data = System::new(0.001);
+    data.collisions.mode = mode;
+    data.collisions.restitution = restitution;
+    data.add(Body::new("a"), [0.0, 0.0, 0.0], [1.0, 0.5, 0.0], 1.0);
+    data.add(Body::new("b"), [0.15, 0.0, 0.0], [-2.0, 0.0, 0.0], 3.0);
+    data.particles.radius[0] = 0.1;
+    data.particles.radius[1] = 0.1;
+    data
+}
+
+fn momentum(data: &System) -> [f64; 3] {
+    let p = &data.particles;
+    let mut s = [0.0, 0.0, 0.0];
+    for i in 0..p.len() {
+        let v = p.v(i);
+        for k in 0..3 {
+            s[k] += p.m[i] * v[k];
+        }
+    }
+    s
+}
+
+fn kinetic(data: &System) -> f64 {
+    let p = &data.particles;
+    let mut e = 0.0;
+    for i in 0..p.len() {
+        let v = p.v(i);
+        e += 0.5 * p.m[i] * (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]);
+    }
+    e
+}
+
+fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    f64::sqrt((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2))
+}
+
+pub fn run_test() {
+    // merge keeps mass, momentum and the center of mass
+    let mut data = two_bodies(Mode::Merge, 0.0);
+    let p0 = momentum(&data);
+    let events = collide(&mut data);
+    let p = &data.particles;
+    let dp = distance(p0, momentum(&data));
+    println!("merge: {:?} {dp}", events);
+    if events != [Event::Merge(0, 1)] || p.len() != 1 || p.m[0] != 4.0 || dp > 1e-15
+        || distance(p.r(0), [0.1125, 0.0, 0.0]) > 1e-15 {
+        println!("Error1"); exit(1);
+    }
+
+    // elastic bounce keeps the kinetic energy, the tangential velocity is untouched
+    let mut data = two_bodies(Mode::Bounce, 1.0);
+    let e0 = kinetic(&data);
+    let events = collide(&mut data);
+    let de = f64::abs(kinetic(&data) / e0 - 1.0);
+    let dp = distance(p0, momentum(&data));
+    println!("bounce 1: {:?} {de} {dp}", events);
+    if events != [Event::Bounce(0, 1)] || de > 1e-15 || dp > 1e-15 || data.particles.vy[0] != 0.5 {
+        println!("Error2"); exit(1);
+    }
+    // separating now, no second bounce
+    if !collide(&mut data).is_empty() {
+        println!("Error3"); exit(1);
+    }
+
+    // perfectly inelastic bounce leaves no normal relative velocity
+    let mut data = two_bodies(Mode::Bounce, 0.0);
+    collide(&mut data);
+    let dv = f64::abs(data.particles.vx[1] - data.particles.vx[0]);
+    println!("bounce 0: {dv}");
+    if dv > 1e-15 {
+        println!("Error4"); exit(1);
+    }
+    println!("Ok");
+}
@@ -0,0 +1,313 @@
+use std::process::exit;
+
+use crate::body::{Body, Particles, System};
+use crate::integrator::Integrator;
+use crate::verlet::Verlet;
+use crate::rk4::Rk4;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    // the body is mirrored back into the shell, the speed is kept
+    Wall,
+    // restoring acceleration k * depth outside the shell, k is per unit mass
+    Spring(f64),
+    // the body stays on the sphere of radius max_rad, RATTLE in verlet
+    Rattle
+}
+
+// keeps body within min_rad <= |r - center| <= max_rad,
+// a bound <= 0 is not enforced
+#[derive(Clone, Debug)]
+pub struct Constraint {
+    pub body: usize,
+    pub center: [f64; 3],
+    pub min_rad: f64,
+    pub max_rad: f64,
+    pub kind: Kind
+}
+
+impl Constraint {
+    pub fn wall(body: usize, center: [f64; 3], min_rad: f64, max_rad: f64) -> Constraint {
+        Constraint { body : body, center : center, min_rad : min_rad, max_rad : max_rad, kind : Kind::Wall }
+    }
+
+    pub fn spring(body: usize, center: [f64; 3], min_rad: f64, max_rad: f64, k: f64) -> Constraint {
+        Constraint { body : body, center : center, min_rad : min_rad, max_rad : max_rad, kind : Kind::Spring(k) }
+    }
+
+    pub fn rattle(body: usize, center: [f64; 3], rad: f64) -> Constraint {
+        Constraint { body : body, center : center, min_rad : rad, max_rad : rad, kind : Kind::Rattle }
+    }
+
+    // offset from the center and its length
+    fn offset(&self, p: &Particles) -> ([f64; 3], f64) {
+        let r = p.r(self.body);
+        let d = [r[0] - self.center[0], r[1] - self.center[1], r[2] - self.center[2]];
+        (d, f64::sqrt(d[0] * d[0] + d[1] * d[1] + d[2] * d[2]))
+    }
+
+    // potential energy per unit mass of a spring, 0 for the other kinds
+    pub fn potential(&self, p: &Particles) -> f64 {
+        let k = match self.kind {
+            Kind::Spring(k) => k,
+            _ => return 0.0
+        };
+        let (_, d) = self.offset(p);
+        let depth = if self.max_rad > 0.0 && d > self.max_rad {
+            d - self.max_rad
+        } else if self.min_rad > 0.0 && d < self.min_rad {
+            self.min_rad - d
+        } else {
+            0.0
+        };
+        0.5 * k * depth * depth
+    }
+}
+
+// spring accelerations on top of p.ax, p.ay, p.az
+pub fn add_springs(p: &mut Particles, constraints: &[Constraint]) {
+    for c in constraints {
+        let k = match c.kind {
+            Kind::Spring(k) => k,
+            _ => continue
+        };
+        let i = c.body;
+        if p.fixed[i] { continue; }
+        let (d, r) = c.offset(p);
+        // positive pulls towards the center
+        let depth = if c.max_rad > 0.0 && r > c.max_rad {
+            r - c.max_rad
+        } else if c.min_rad > 0.0 && r < c.min_rad {
+            r - c.min_rad
+        } else {
+            continue
+        };
+        if r == 0.0 { continue; }
+        let mut a = p.a(i);
+        for k3 in 0..3 {
+            a[k3] -= k * depth * d[k3] / r;
+        }
+        p.set_a(i, a);
+    }
+}
+
+// mirrors bodies that crossed a wall back inside and flips the normal velocity,
+// returns true when something was reflected
+pub fn reflect(p: &mut Particles, constraints: &[Constraint]) -> bool {
+    let mut changed = false;
+    for c in constraints {
+        if c.kind != Kind::Wall { continue; }
+        let i = c.body;
+        if p.fixed[i] { continue; }
+        let (d, r) = c.offset(p);
+        let target = if c.max_rad > 0.0 && r > c.max_rad {
+            2.0 * c.max_rad - r
+        } else if c.min_rad > 0.0 && r < c.min_rad {
+            2.0 * c.min_rad - r
+        } else {
+            continue
+        };
+        if r == 0.0 { continue; }
+        let n = [d[0] / r, d[1] / r, d[2] / r];
+        let mut v = p.v(i);
+        let vn = v[0] * n[0] + v[1] * n[1] + v[2] * n[2];
+        for k in 0..3 {
+            v[k] -= 2.0 * vn * n[k];
+        }
+        let target = target.max(0.0);
+        p.set_r(i, [c.center[0] + target * n[0], c.center[1] + target * n[1], c.center[2] + target * n[2]]);
+        p.set_v(i, v);
+        changed = true;
+    }
+    changed
+}
+
+// positions of the rattle bodies relative to their centers before a drift
+pub fn anchors(p: &Particles, constraints: &[Constraint]) -> Vec<[f64; 3]> {
+    constraints.iter().map(|c| c.offset(p).0).collect()
+}
+
+// RATTLE position stage after a drift of length h: moves each body along the
+// constraint gradient at the start of the drift (old) back onto its sphere
+// and adds the same impulse to the velocity
+pub fn shake(p: &mut Particles, constraints: &[Constraint], old: &[[f64; 3]], h: f64) {
+    for (c, o) in constraints.iter().zip(old) {
+        if c.kind != Kind::Rattle { continue; }
+        let i = c.body;
+        if p.fixed[i] { continue; }
+        let (d, _) = c.offset(p);
+        // |d + s o|^2 = R^2, the root closest to 0
+        let qa = o[0] * o[0] + o[1] * o[1] + o[2] * o[2];
+        let qb = 2.0 * (d[0] * o[0] + d[1] * o[1] + d[2] * o[2]);
+        let qc = d[0] * d[0] + d[1] * d[1] + d[2] * d[2] - c.max_rad * c.max_rad;
+        let disc = qb * qb - 4.0 * qa * qc;
+        if qa == 0.0 || disc < 0.0 { continue; }
+        let q = -0.5 * (qb + qb.signum() * disc.sqrt());
+        let s = if q == 0.0 { 0.0 } else { qc / q };
+        let (mut r, mut v) = (p.r(i), p.v(i));
+        for k in 0..3 {
+            r[k] += s * o[k];
+            v[k] += s * o[k] / h;
+        }
+        p.set_r(i, r);
+        p.set_v(i, v);
+    }
+}
+
+// RATTLE velocity stage: removes the velocity along the sphere normal
+pub fn rattle(p: &mut Particles, constraints: &[Constraint]) {
+    for c in constraints {
+        if c.kind != Kind::Rattle { continue; }
+        let i = c.body;
+        if p.fixed[i] { continue; }
+        let (d, r) = c.offset(p);
+        if r == 0.0 { continue; }
+        let mut v = p.v(i);
+        let vn = (v[0] * d[0] + v[1] * d[1] + v[2] * d[2]) / r;
+        for k in 0..3 {
+            v[k] -= vn * d[k] / r;
+        }
+        p.set_v(i, v);
+    }
+}
+
+// relative distance from the sphere and normal velocity below which a
+// rattle body is left alone by enforce
+const TOLERANCE: f64 = 1e-12;
+
+// after a step of an integrator that does not handle constraints itself:
+// reflects at walls and projects rattle bodies radially onto their spheres,
+// returns true when the state was changed
+pub fn enforce(data: &mut System) -> bool {
+    let constraints = &data.forces.constraints;
+    let p = &mut data.particles;
+    let mut changed = reflect(p, constraints);
+    for c in constraints {
+        if c.kind != Kind::Rattle { continue; }
+        let i = c.body;
+        if p.fixed[i] { continue; }
+        let (d, r) = c.offset(p);
+        if r == 0.0 { continue; }
+        let s = c.max_rad / r;
+        if f64::abs(s - 1.0) > TOLERANCE {
+            p.set_r(i, [c.center[0] + s * d[0], c.center[1] + s * d[1], c.center[2] + s * d[2]]);
+            changed = true;
+        }
+        // the velocity stage of rattle, only when it changes something
+        let (d, r) = c.offset(p);
+        let mut v = p.v(i);
+        let speed = f64::sqrt(v[0] * v[0] + v[1] * v[1] + v[2] * v[2]);
+        let vn = (v[0] * d[0] + v[1] * d[1] + v[2] * d[2]) / r;
+        if f64::abs(vn) > TOLERANCE * speed {
+            for k in 0..3 {
+                v[k] -= vn * d[k] / r;
+            }
+            p.set_v(i, v);
+            changed = true;
+        }
+    }
+    changed
+}
+
+fn energy(data: &System) -> f64 {
+    let p = &data.particles;
+    let mut e = 0.0;
+    for i in 0..p.len() {
+        let v = p.v(i);
+        e += 0.5 * p.m[i] * (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]);
+        for j in i + 1..p.len() {
+            let (ri, rj) = (p.r(i), p.r(j));
+            let d = f64::sqrt((ri[0] - rj[0]).powi(2) + (ri[1] - rj[1]).powi(2) + (ri[2] - rj[2]).powi(2));
+            e -= data.g * p.m[i] * p.m[j] / d;
+        }
+    }
+    for c in &data.forces.constraints {
+        e += p.m[c.body] * c.potential(p);
+    }
+    e
+}
+
+// max relative energy error and max violation of the constraint of body 1
+fn run(data: &mut System, method: &mut dyn Integrator, steps: usize) -> (f64, f64) {
+    let e0 = energy(data);
+    let c = data.forces.constraints[0].clone();
+    let mut max_err: f64 = 0.0;
+    let mut max_out: f64 = 0.0;
+    method.init(data);
+    for _ in 0..steps {
+        method.step(data);
+        if !method.handles_constraints() && enforce(data) {
+            method.init(data);
+        }
+        max_err = max_err.max(f64::abs(energy(data) / e0 - 1.0));
+        let (_, r) = c.offset(&data.particles);
+        let out = if c.kind == Kind::Rattle {
+            f64::abs(r - c.max_rad)
+        } else {
+            f64::max(r - c.max_rad, c.min_rad - r).max(0.0)
+        };
+        max_out = max_out.max(out);
+    }
+    (max_err, max_out)
+}
+
+// body 1 circles a fixed attractor inside a shell around (2, 0, 0)
+fn shell(constraint: Constraint) -> System {
+    let mut data = System::new(0.001);
+    data.add(Body::new("star"), [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 1.0);
+    data.add(Body::new("p"), [2.5, 0.0, 0.0], [0.0, 1.0, 0.3], 1.0);
+    data.particles.fixed[0] = true;
+    data.forces.constraints.push(constraint);
+    data
+}
+
+pub fn run_test() {
+    let center = [2.0, 0.0, 0.0];
+
+    // a hard wall costs O(dt) energy at every reflection, but nothing
+    // accumulates systematically as with the old radial projection
+    let mut data = shell(Constraint::wall(1, center, 0.2, 0.7));
+    let (err, out) = run(&mut data, &mut Verlet, 20000);
+    println!("wall verlet: {err} {out}");
+    if err > 1e-2 || out > 0.0 {
+        println!("Error1"); exit(1);
+    }
+    let mut data = shell(Constraint::wall(1, center, 0.2, 0.7));
+    let (err, out) = run(&mut data, &mut Rk4::new(), 20000);
+    println!("wall rk4: {err} {out}");
+    if err > 3e-2 || out > 0.0 {
+        println!("Error2"); exit(1);
+    }
+
+    // a spring is a potential, so the error is bounded and converges with dt;
+    // its second derivative jumps at the shell, which limits the order
+    let mut data = shell(Constraint::spring(1, center, 0.2, 0.7, 1000.0));
+    let (err1, _) = run(&mut data, &mut Verlet, 20000);
+    let mut data = shell(Constraint::spring(1, center, 0.2, 0.7, 1000.0));
+    data.dt = 0.0005;
+    let (err2, _) = run(&mut data, &mut Verlet, 40000);
+    println!("spring verlet: {err1} {err2}");
+    if err1 > 3e-3 || err2 > err1 / 1.5 {
+        println!("Error3"); exit(1);
+    }
+
+    let mut data = shell(Constraint::rattle(1, center, 0.5));
+    let (err, out) = run(&mut data, &mut Verlet, 20000);
+    println!("rattle verlet: {err} {out}");
+    if err > 1e-5 || out > 1e-12 {
+        println!("Error4"); exit(1);
+    }
+
+    // a body on its sphere moving along it is left alone, so the
+    // integrators keep their state
+    let mut data = shell(Constraint::rattle(1, center, 0.5));
+    data.particles.set_v(1, [0.0, 1.0, 0.3]);
+    let unchanged = !enforce(&mut data);
+    data.particles.set_r(1, [2.6, 0.0, 0.0]);
+    let moved = enforce(&mut data);
+    println!("rattle enforce: {unchanged} {moved}");
+    if !unchanged || !moved || data.particles.x[1] != 2.5 {
+        println!("Error5"); exit(1);
+    }
+    println!("Ok");
+}
@@ -0,0 +1,105 @@
+use std::process::exit;
+
+use crate::body::{Body, System};
+use crate::integrator::Integrator;
+use crate::field;
+
+// conserved quantities of the whole system
+#[derive(Clone, Debug)]
+pub struct Diagnostics {
+    pub kinetic: f64,
+    // softened gravity, springs and external fields; drag, radiation and
+    // the 1PN correction have no potential here
+    pub potential: f64,
+    pub momentum: [f64; 3],
+    // about the origin
+    pub angular_momentum: [f64; 3],
+    pub center_of_mass: [f64; 3]
+}
+
+impl Diagnostics {
+    pub fn energy(&self) -> f64 {
+        self.kinetic + self.potential
+    }
+}
+
+// O(N^2), the pair sum does not use the tree or the threads
+pub fn measure(data: &System) -> Diagnostics {
+    let p = &data.particles;
+    let n = p.len();
+    let eps2 = data.forces.softening * data.forces.softening;
+    let mut d = Diagnostics {
+        kinetic : 0.0,
+        potential : 0.0,
+        momentum : [0.0, 0.0, 0.0],
+        angular_momentum : [0.0, 0.0, 0.0],
+        center_of_mass : [0.0, 0.0, 0.0]
+    };
+    let mut mass = 0.0;
+    for i in 0..n {
+        let (r, v, m) = (p.r(i), p.v(i), p.m[i]);
+        d.kinetic += 0.5 * m * (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]);
+        d.angular_momentum[0] += m * (r[1] * v[2] - r[2] * v[1]);
+        d.angular_momentum[1] += m * (r[2] * v[0] - r[0] * v[2]);
+        d.angular_momentum[2] += m * (r[0] * v[1] - r[1] * v[0]);
+        for k in 0..3 {
+            d.momentum[k] += m * v[k];
+            d.center_of_mass[k] += m * r[k];
+        }
+        mass += m;
+        for j in i + 1..n {
+            let rj = p.r(j);
+            let r2 = (r[0] - rj[0]).powi(2) + (r[1] - rj[1]).powi(2) + (r[2] - rj[2]).powi(2);
+            d.potential -= data.g * m * p.m[j] / f64::sqrt(r2 + eps2);
+        }
+    }
+    if mass > 0.0 {
+        for k in 0..3 {
+            d.center_of_mass[k] /= mass;
+        }
+    }
+    for c in &data.forces.constraints {
+        d.potential += p.m[c.body] * c.potential(p);
+    }
+    d.potential += field::potential(p, data.g, &data.forces.fields);
+    d
+}
+
+fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    f64::sqrt((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2))
+}
+
+pub fn run_test(method: &mut dyn Integrator) {
+    // two bodies: K = 0.5 + 2.25, U = -2 / 2, p = (0, 4, 0),
+    // L = 2 * 2 * 1.5 along z and the center of mass at x = 4/3
+    let mut data = System::new(0.001);
+    data.add(Body::new("a"), [0.0, 0.0, 0.0], [0.0, 1.0, 0.0], 1.0);
+    data.add(Body::new("b"), [2.0, 0.0, 0.0], [0.0, 1.5, 0.0], 2.0);
+    let d = measure(&data);
+    println!("diagnostics: {:?}", d);
+    if f64::abs(d.kinetic - 2.75) > 1e-15 || f64::abs(d.potential + 1.0) > 1e-15
+        || distance(d.momentum, [0.0, 4.0, 0.0]) > 1e-15
+        || distance(d.angular_momentum, [0.0, 0.0, 6.0]) > 1e-15
+        || distance(d.center_of_mass, [4.0 / 3.0, 0.0, 0.0]) > 1e-15 {
+        println!("Error1"); exit(1);
+    }
+
+    // pairwise gravity keeps the momentum to roundoff with every method
+    let mut data = System::new(0.001);
+    data.add(Body::new("star"), [0.0, 0.0, 0.0], [0.0, -0.01, 0.0], 1.0);
+    data.add(Body::new("p1"), [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], 1e-2);
+    data.add(Body::new("p2"), [0.0, 1.6, 0.2], [-0.8, 0.0, 0.0], 1e-2);
+    let d0 = measure(&data);
+    method.init(&mut data);
+    for _ in 0..1000 {
+        method.step(&mut data);
+    }
+    let d = measure(&data);
+    let dp = distance(d.momentum, d0.momentum);
+    let de = f64::abs(d.energy() / d0.energy() - 1.0);
+    println!("drift: momentum {dp} energy {de}");
+    if dp > 1e-13 {
+        println!("Error2"); exit(1);
+    }
+    println!("Ok");
+}
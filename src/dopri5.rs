@@ -0,0 +1,233 @@
+use crate::body::{Particles, System};
+use crate::gravity::accelerations;
+use crate::integrator::Integrator;
+
+// Dormand-Prince 5(4) tableau, the system is autonomous so the nodes
+// are not needed
+const A: [[f64; 6]; 7] = [
+    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [19372.0 / 6561.0, -25360.0 / 2187.0, 64448.0 / 6561.0, -212.0 / 729.0, 0.0, 0.0],
+    [9017.0 / 3168.0, -355.0 / 33.0, 46732.0 / 5247.0, 49.0 / 176.0, -5103.0 / 18656.0, 0.0],
+    [35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0, 11.0 / 84.0],
+];
+// difference between the 5th and the embedded 4th order weights
+const E: [f64; 7] = [
+    71.0 / 57600.0, 0.0, -71.0 / 16695.0, 71.0 / 1920.0,
+    -17253.0 / 339200.0, 22.0 / 525.0, -1.0 / 40.0
+];
+
+// adaptive embedded Runge-Kutta, data.dt is the output interval,
+// the internal step is chosen from rtol/atol
+pub struct Dopri5 {
+    pub rtol: f64,
+    pub atol: f64,
+    // internal step
+    h: f64,
+    // last internal step goes from (t0, y0) to (t1, y1),
+    // y is r0 r1 r2 v0 v1 v2 for every body
+    t0: f64,
+    t1: f64,
+    y0: Vec<f64>,
+    y1: Vec<f64>,
+    // stage derivatives, k[0] is f(y1) after a step (FSAL)
+    k: [Vec<f64>; 7],
+    // f(y0) of the last step, used for interpolation
+    f0: Vec<f64>,
+    // time of data.particles
+    t_out: f64,
+    stage: Particles,
+    // a step above the tolerance was accepted, warn only once
+    warned: bool
+}
+
+impl Dopri5 {
+    pub fn new(rtol: f64, atol: f64) -> Dopri5 {
+        Dopri5 {
+            rtol : rtol,
+            atol : atol,
+            h : 0.0,
+            t0 : 0.0,
+            t1 : 0.0,
+            y0 : Vec::new(),
+            y1 : Vec::new(),
+            k : Default::default(),
+            f0 : Vec::new(),
+            t_out : 0.0,
+            stage : Particles::new(),
+            warned : false
+        }
+    }
+
+    // dy/dt = (v, a), fixed bodies do not move
+    fn derivs(&mut self, data: &System, y: &[f64], f: &mut Vec<f64>) {
+        let n = self.stage.len();
+        for i in 0..n {
+            self.stage.set_r(i, [y[6 * i], y[6 * i + 1], y[6 * i + 2]]);
+            self.stage.set_v(i, [y[6 * i + 3], y[6 * i + 4], y[6 * i + 5]]);
+        }
+        accelerations(&mut self.stage, data.g, &data.forces);
+        f.resize(y.len(), 0.0);
+        for i in 0..n {
+            let a = self.stage.a(i);
+            let fixed = self.stage.fixed[i];
+            for k in 0..3 {
+                f[6 * i + k] = if fixed { 0.0 } else { y[6 * i + 3 + k] };
+                f[6 * i + 3 + k] = a[k];
+            }
+        }
+    }
+
+    // one accepted step from (t1, y1), rejected attempts shrink h
+    fn advance(&mut self, data: &System) {
+        let m = self.y1.len();
+        let mut y = vec![0.0; m];
+        let mut k = std::mem::take(&mut self.k);
+
+        loop {
+            let h = self.h;
+            for s in 1..7 {
+                for j in 0..m {
+                    let mut sum = 0.0;
+                    for l in 0..s {
+                        sum += A[s][l] * k[l][j];
+                    }
+                    y[j] = self.y1[j] + h * sum;
+                }
+                self.derivs(data, &y, &mut k[s]);
+            }
+            // the last stage row is the 5th order solution, so y is the new state
+            // and k[6] = f(y)
+
+            let mut err = 0.0;
+            for j in 0..m {
+                let mut e = 0.0;
+                for s in 0..7 {
+                    e += E[s] * k[s][j];
+                }
+                let sc = self.atol + self.rtol * f64::max(self.y1[j].abs(), y[j].abs());
+                err += (h * e / sc) * (h * e / sc);
+            }
+            err = f64::sqrt(err / m.max(1) as f64);
+
+            let factor = if err == 0.0 { 5.0 } else { 0.9 * err.powf(-0.2) };
+            let factor = if err.is_nan() { 0.2 } else { factor.clamp(0.2, 5.0) };
+
+            // accept, or give up shrinking once h is below the time resolution
+            if err <= 1.0 || h <= f64::EPSILON * self.t1.abs().max(1.0) {
+                if !self.warned && (err.is_nan() || err > 1.0) {
+                    eprintln!("dopri5: step size underflow at t = {}, accepting error {} times the tolerance", self.t1, err);
+                    self.warned = true;
+                }
+                self.t0 = self.t1;
+                self.t1 += h;
+                std::mem::swap(&mut self.y0, &mut self.y1);
+                self.y1.clone_from(&y);
+                std::mem::swap(&mut self.f0, &mut k[0]);
+                k.swap(0, 6);
+                self.h = h * factor;
+                break;
+            }
+            self.h = h * factor;
+        }
+
+        self.k = k;
+    }
+
+    // quintic Hermite interpolation of r and its derivative for v
+    fn interpolate(&self, t: f64, data: &mut System) {
+        let h = self.t1 - self.t0;
+        let th = if h > 0.0 { (t - self.t0) / h } else { 1.0 };
+        let (t2, t3, t4, t5) = (th * th, th * th * th, th * th * th * th, th * th * th * th * th);
+
+        let h0 = 1.0 - 10.0 * t3 + 15.0 * t4 - 6.0 * t5;
+        let h1 = th - 6.0 * t3 + 8.0 * t4 - 3.0 * t5;
+        let h2 = 0.5 * (t2 - 3.0 * t3 + 3.0 * t4 - t5);
+        let h3 = 0.5 * (t3 - 2.0 * t4 + t5);
+        let h4 = -4.0 * t3 + 7.0 * t4 - 3.0 * t5;
+        let h5 = 10.0 * t3 - 15.0 * t4 + 6.0 * t5;
+
+        let d0 = -30.0 * t2 + 60.0 * t3 - 30.0 * t4;
+        let d1 = 1.0 - 18.0 * t2 + 32.0 * t3 - 15.0 * t4;
+        let d2 = 0.5 * (2.0 * th - 9.0 * t2 + 12.0 * t3 - 5.0 * t4);
+        let d3 = 0.5 * (3.0 * t2 - 8.0 * t3 + 5.0 * t4);
+        let d4 = -12.0 * t2 + 28.0 * t3 - 15.0 * t4;
+        let d5 = 30.0 * t2 - 60.0 * t3 + 30.0 * t4;
+
+        let (y0, y1, f0, f1) = (&self.y0, &self.y1, &self.f0, &self.k[0]);
+        let p = &mut data.particles;
+        for i in 0..p.len() {
+            if p.fixed[i] { continue; }
+            let (mut r, mut v, mut a) = (p.r(i), p.v(i), [0.0, 0.0, 0.0]);
+            for k in 0..3 {
+                let (r0, v0, a0) = (y0[6 * i + k], y0[6 * i + 3 + k], f0[6 * i + 3 + k]);
+                let (r1, v1, a1) = (y1[6 * i + k], y1[6 * i + 3 + k], f1[6 * i + 3 + k]);
+                r[k] = h0 * r0 + h * h1 * v0 + h * h * h2 * a0
+                    + h5 * r1 + h * h4 * v1 + h * h * h3 * a1;
+                if h > 0.0 {
+                    v[k] = (d0 * r0 + h * d1 * v0 + h * h * d2 * a0
+                        + d5 * r1 + h * d4 * v1 + h * h * d3 * a1) / h;
+                }
+                a[k] = a0 + th * (a1 - a0);
+            }
+            p.set_r(i, r);
+            p.set_v(i, v);
+            p.set_a(i, a);
+        }
+    }
+}
+
+impl Integrator for Dopri5 {
+    fn name(&self) -> &'static str {
+        "dopri5"
+    }
+
+    fn order(&self) -> u32 {
+        5
+    }
+
+    fn adaptive(&self) -> bool {
+        true
+    }
+
+    fn set_tolerance(&mut self, rtol: f64, atol: f64) {
+        self.rtol = rtol;
+        self.atol = atol;
+    }
+
+    fn init(&mut self, data: &mut System) {
+        let n = data.particles.len();
+        self.stage.clone_from(&data.particles);
+        self.y1 = vec![0.0; 6 * n];
+        for i in 0..n {
+            let (r, v) = (data.particles.r(i), data.particles.v(i));
+            for k in 0..3 {
+                self.y1[6 * i + k] = r[k];
+                self.y1[6 * i + 3 + k] = v[k];
+            }
+        }
+        self.y0.clone_from(&self.y1);
+        for s in 0..7 {
+            self.k[s] = vec![0.0; 6 * n];
+        }
+        let y = self.y1.clone();
+        let mut f = Vec::new();
+        self.derivs(data, &y, &mut f);
+        self.f0.clone_from(&f);
+        self.k[0] = f;
+        self.t0 = 0.0;
+        self.t1 = 0.0;
+        self.t_out = 0.0;
+        self.h = data.dt;
+    }
+
+    fn step(&mut self, data: &mut System) {
+        self.t_out += data.dt;
+        while self.t1 < self.t_out {
+            self.advance(data);
+        }
+        self.interpolate(self.t_out, data);
+    }
+}
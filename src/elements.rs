@@ -0,0 +1,197 @@
+use std::f64::consts::PI;
+use std::process::exit;
+
+use crate::body::Particles;
+
+// osculating Keplerian elements, angles in radians; a < 0 and e > 1 on
+// hyperbolic orbits, where the mean anomaly is e sinh H - H and the
+// period is infinite
+#[derive(Clone, Copy, Debug)]
+pub struct Elements {
+    pub a: f64,
+    pub e: f64,
+    pub i: f64,
+    // longitude of the ascending node
+    pub node: f64,
+    // argument of pericenter
+    pub peri: f64,
+    pub mean_anomaly: f64,
+    pub period: f64
+}
+
+// below this e the pericenter is measured from the node, and below this
+// sin i the node from the x axis
+const CIRCULAR: f64 = 1e-12;
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+    f64::sqrt(dot(a, a))
+}
+
+// angle from u to w about the axis h
+fn angle(u: [f64; 3], w: [f64; 3], h: [f64; 3]) -> f64 {
+    f64::atan2(dot(cross(u, w), h), dot(u, w))
+}
+
+fn wrap(x: f64) -> f64 {
+    x.rem_euclid(2.0 * PI)
+}
+
+// elements of relative position r and velocity v, mu = G (m1 + m2)
+pub fn from_state(r: [f64; 3], v: [f64; 3], mu: f64) -> Elements {
+    let h = cross(r, v);
+    let hn = norm(h);
+    let hu = [h[0] / hn, h[1] / hn, h[2] / hn];
+    let d = norm(r);
+    let vh = cross(v, h);
+    let ev = [vh[0] / mu - r[0] / d, vh[1] / mu - r[1] / d, vh[2] / mu - r[2] / d];
+    let e = norm(ev);
+    let a = 1.0 / (2.0 / d - dot(v, v) / mu);
+    let i = f64::acos((h[2] / hn).clamp(-1.0, 1.0));
+
+    // node line z x h, the x axis on equatorial orbits
+    let n = [-h[1], h[0], 0.0];
+    let nn = norm(n);
+    let (reference, node) = if nn > CIRCULAR * hn {
+        ([n[0] / nn, n[1] / nn, 0.0], wrap(f64::atan2(n[1], n[0])))
+    } else {
+        ([1.0, 0.0, 0.0], 0.0)
+    };
+    let pericenter = if e > CIRCULAR { [ev[0] / e, ev[1] / e, ev[2] / e] } else { reference };
+    let peri = wrap(angle(reference, pericenter, hu));
+    let nu = angle(pericenter, r, hu);
+
+    let (mean_anomaly, period) = if e < 1.0 {
+        let ea = 2.0 * f64::atan(f64::sqrt((1.0 - e) / (1.0 + e)) * f64::tan(0.5 * nu));
+        (wrap(ea - e * ea.sin()), 2.0 * PI * f64::sqrt(a * a * a / mu))
+    } else {
+        let ha = 2.0 * f64::atanh(f64::sqrt((e - 1.0) / (e + 1.0)) * f64::tan(0.5 * nu));
+        (e * ha.sinh() - ha, f64::INFINITY)
+    };
+    Elements { a : a, e : e, i : i, node : node, peri : peri, mean_anomaly : mean_anomaly, period : period }
+}
+
+// inverse of from_state, the period is not used
+pub fn to_state(el: &Elements, mu: f64) -> ([f64; 3], [f64; 3]) {
+    let (e, m) = (el.e, el.mean_anomaly);
+    // Kepler's equation by Newton's method
+    let nu = if e < 1.0 {
+        let mut ea = if e > 0.8 { PI } else { m };
+        for _ in 0..50 {
+            let dx = (ea - e * ea.sin() - m) / (1.0 - e * ea.cos());
+            ea -= dx;
+            if dx.abs() < 1e-15 { break; }
+        }
+        2.0 * f64::atan2(f64::sqrt(1.0 + e) * f64::sin(0.5 * ea), f64::sqrt(1.0 - e) * f64::cos(0.5 * ea))
+    } else {
+        let mut ha = f64::asinh(m / e);
+        for _ in 0..50 {
+            let dx = (e * ha.sinh() - ha - m) / (e * ha.cosh() - 1.0);
+            ha -= dx;
+            if dx.abs() < 1e-15 { break; }
+        }
+        2.0 * f64::atan(f64::sqrt((e + 1.0) / (e - 1.0)) * f64::tanh(0.5 * ha))
+    };
+    state_at(el, nu, mu)
+}
+
+// position and velocity at true anomaly nu, the mean anomaly is not used
+pub fn state_at(el: &Elements, nu: f64, mu: f64) -> ([f64; 3], [f64; 3]) {
+    let (a, e) = (el.a, el.e);
+    let p = a * (1.0 - e * e);
+    let d = p / (1.0 + e * nu.cos());
+    let s = f64::sqrt(mu / p);
+    // perifocal frame, then rotated by the node, the inclination and the pericenter
+    let rp = [d * nu.cos(), d * nu.sin()];
+    let vp = [-s * nu.sin(), s * (e + nu.cos())];
+    let (so, co) = el.node.sin_cos();
+    let (si, ci) = el.i.sin_cos();
+    let (sw, cw) = el.peri.sin_cos();
+    let px = [co * cw - so * sw * ci, so * cw + co * sw * ci, sw * si];
+    let qx = [-co * sw - so * cw * ci, -so * sw + co * cw * ci, cw * si];
+    let mut r = [0.0, 0.0, 0.0];
+    let mut v = [0.0, 0.0, 0.0];
+    for k in 0..3 {
+        r[k] = rp[0] * px[k] + rp[1] * qx[k];
+        v[k] = vp[0] * px[k] + vp[1] * qx[k];
+    }
+    (r, v)
+}
+
+// what is wrong with the a and e of a conic state_at cannot place, as the
+// index of the element to blame (0 for a, 1 for e) and a description: e is
+// at least 0 and not 1, as a parabola has no finite a, and a is positive
+// below e = 1 and negative above
+pub fn conic_error(a: f64, e: f64) -> Option<(usize, &'static str)> {
+    if !e.is_finite() || e < 0.0 || e == 1.0 {
+        Some((1, "eccentricity"))
+    } else if e < 1.0 && (!a.is_finite() || a <= 0.0) {
+        Some((0, "semi-major axis for e < 1"))
+    } else if e > 1.0 && (!a.is_finite() || a >= 0.0) {
+        Some((0, "semi-major axis for e > 1"))
+    } else {
+        None
+    }
+}
+
+// the most massive body, the default primary
+pub fn heaviest(p: &Particles) -> usize {
+    let mut primary = 0;
+    for i in 0..p.len() {
+        if p.m[i] > p.m[primary] {
+            primary = i;
+        }
+    }
+    primary
+}
+
+// elements of body i around the primary, None for the primary itself
+pub fn relative(p: &Particles, g: f64, primary: usize, i: usize) -> Option<Elements> {
+    if i == primary {
+        return None;
+    }
+    let (ri, rp, vi, vp) = (p.r(i), p.r(primary), p.v(i), p.v(primary));
+    let r = [ri[0] - rp[0], ri[1] - rp[1], ri[2] - rp[2]];
+    let v = [vi[0] - vp[0], vi[1] - vp[1], vi[2] - vp[2]];
+    Some(from_state(r, v, g * (p.m[primary] + p.m[i])))
+}
+
+fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    norm([a[0] - b[0], a[1] - b[1], a[2] - b[2]])
+}
+
+pub fn run_test() {
+    // circular orbit in the xy plane
+    let el = from_state([2.0, 0.0, 0.0], [0.0, 0.5, 0.0], 0.5);
+    println!("circular: {:?}", el);
+    if f64::abs(el.a - 2.0) > 1e-15 || el.e > 1e-15 || el.i != 0.0
+        || f64::abs(el.period - 4.0 * PI * 2.0) > 1e-13 {
+        println!("Error1"); exit(1);
+    }
+
+    // state -> elements -> state for inclined, retrograde and hyperbolic orbits
+    let cases = [
+        ([1.0, 0.2, 0.3], [-0.1, 0.9, 0.2], 1.0),
+        ([0.5, -1.0, 0.1], [0.3, 0.2, -0.7], 2.0),
+        ([1.0, 0.0, 0.0], [0.0, -1.1, 0.0], 1.0),
+        ([1.0, 1.0, 0.5], [1.0, -0.5, 0.8], 1.0),
+    ];
+    let mut max_err: f64 = 0.0;
+    for (r, v, mu) in cases {
+        let el = from_state(r, v, mu);
+        let (r1, v1) = to_state(&el, mu);
+        max_err = max_err.max(distance(r, r1)).max(distance(v, v1));
+    }
+    println!("round trip: {max_err}");
+    if max_err > 1e-13 {
+        println!("Error2"); exit(1);
+    }
+    println!("Ok");
+}
@@ -0,0 +1,196 @@
+use std::process::exit;
+
+use crate::body::{Body, Particles, System};
+use crate::integrator::Integrator;
+use crate::dopri5::Dopri5;
+
+// external accelerations added on top of the pairwise sum
+#[derive(Clone, Debug)]
+pub enum Field {
+    // zonal harmonic of an oblate body with equatorial radius `radius`
+    // and unit symmetry axis `axis`; the body feels the reaction
+    J2 { body: usize, j2: f64, radius: f64, axis: [f64; 3] },
+    // the same acceleration everywhere
+    Uniform { g: [f64; 3] },
+    // a = -k (r - center)
+    Harmonic { center: [f64; 3], k: f64 }
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+// J2 acceleration at offset d from the oblate body of gravitational parameter mu
+fn j2_acceleration(d: [f64; 3], mu: f64, j2: f64, radius: f64, axis: [f64; 3]) -> [f64; 3] {
+    let r2 = dot(d, d);
+    let r = f64::sqrt(r2);
+    let z = dot(d, axis);
+    let f = 1.5 * j2 * mu * radius * radius / (r2 * r2 * r);
+    let s = 5.0 * z * z / r2 - 1.0;
+    [
+        f * (s * d[0] - 2.0 * z * axis[0]),
+        f * (s * d[1] - 2.0 * z * axis[1]),
+        f * (s * d[2] - 2.0 * z * axis[2])
+    ]
+}
+
+// field accelerations on top of p.ax, p.ay, p.az, fixed particles are skipped
+pub fn add_fields(p: &mut Particles, g: f64, fields: &[Field]) {
+    for field in fields {
+        match *field {
+            Field::J2 { body, j2, radius, axis } => {
+                let rb = p.r(body);
+                let mu = g * p.m[body];
+                let mut reaction = [0.0, 0.0, 0.0];
+                for i in 0..p.len() {
+                    if i == body { continue; }
+                    let ri = p.r(i);
+                    let a = j2_acceleration([ri[0] - rb[0], ri[1] - rb[1], ri[2] - rb[2]], mu, j2, radius, axis);
+                    for k in 0..3 {
+                        reaction[k] -= p.m[i] * a[k] / p.m[body];
+                    }
+                    if p.fixed[i] { continue; }
+                    p.ax[i] += a[0];
+                    p.ay[i] += a[1];
+                    p.az[i] += a[2];
+                }
+                if !p.fixed[body] {
+                    p.ax[body] += reaction[0];
+                    p.ay[body] += reaction[1];
+                    p.az[body] += reaction[2];
+                }
+            }
+            Field::Uniform { g } => {
+                for i in 0..p.len() {
+                    if p.fixed[i] { continue; }
+                    p.ax[i] += g[0];
+                    p.ay[i] += g[1];
+                    p.az[i] += g[2];
+                }
+            }
+            Field::Harmonic { center, k } => {
+                for i in 0..p.len() {
+                    if p.fixed[i] { continue; }
+                    p.ax[i] -= k * (p.x[i] - center[0]);
+                    p.ay[i] -= k * (p.y[i] - center[1]);
+                    p.az[i] -= k * (p.z[i] - center[2]);
+                }
+            }
+        }
+    }
+}
+
+// total potential energy of the fields
+pub fn potential(p: &Particles, g: f64, fields: &[Field]) -> f64 {
+    let mut u = 0.0;
+    for field in fields {
+        match *field {
+            Field::J2 { body, j2, radius, axis } => {
+                let rb = p.r(body);
+                let mu = g * p.m[body];
+                for i in 0..p.len() {
+                    if i == body { continue; }
+                    let ri = p.r(i);
+                    let d = [ri[0] - rb[0], ri[1] - rb[1], ri[2] - rb[2]];
+                    let r2 = dot(d, d);
+                    let r = f64::sqrt(r2);
+                    let z = dot(d, axis);
+                    u += p.m[i] * mu * j2 * radius * radius * (3.0 * z * z / r2 - 1.0) / (2.0 * r2 * r);
+                }
+            }
+            Field::Uniform { g } => {
+                for i in 0..p.len() {
+                    u -= p.m[i] * dot(g, p.r(i));
+                }
+            }
+            Field::Harmonic { center, k } => {
+                for i in 0..p.len() {
+                    let ri = p.r(i);
+                    let d = [ri[0] - center[0], ri[1] - center[1], ri[2] - center[2]];
+                    u += 0.5 * k * p.m[i] * dot(d, d);
+                }
+            }
+        }
+    }
+    u
+}
+
+// planet around a free oblate star with a tilted axis, in all three fields
+fn scenario(dt: f64, fields: bool) -> System {
+    let mut data = System::new(dt);
+    data.add(Body::new("star"), [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 1.0);
+    data.add(Body::new("p"), [1.0, 0.0, 0.0], [0.0, 0.9, 0.2], 1e-2);
+    if fields {
+        let (s, c) = f64::sin_cos(0.3);
+        data.forces.fields.push(Field::J2 { body : 0, j2 : 0.1, radius : 0.5, axis : [s, 0.0, c] });
+        data.forces.fields.push(Field::Uniform { g : [0.02, 0.0, 0.01] });
+        data.forces.fields.push(Field::Harmonic { center : [0.5, 0.5, 0.0], k : 0.05 });
+    }
+    data
+}
+
+fn max_distance(a: &System, b: &System) -> f64 {
+    let mut max_err: f64 = 0.0;
+    for i in 0..a.particles.len() {
+        let (ra, rb) = (a.particles.r(i), b.particles.r(i));
+        for k in 0..3 {
+            max_err = max_err.max(f64::abs(ra[k] - rb[k]));
+        }
+    }
+    max_err
+}
+
+fn integrate(mut data: System, method: &mut dyn Integrator, max_time: f64) -> System {
+    let steps = (max_time / data.dt).round() as usize;
+    method.init(&mut data);
+    for _ in 0..steps {
+        method.step(&mut data);
+    }
+    data
+}
+
+pub fn run_test(method: &mut dyn Integrator) {
+    // the accelerations are minus the gradient of the potential,
+    // checked by central differences on body 1 and the star
+    let mut data = scenario(1.0, true);
+    let fields = data.forces.fields.clone();
+    let p = &mut data.particles;
+    p.set_a(0, [0.0, 0.0, 0.0]);
+    p.set_a(1, [0.0, 0.0, 0.0]);
+    add_fields(p, 1.0, &fields);
+    let mut max_err: f64 = 0.0;
+    for i in 0..2 {
+        let r = p.r(i);
+        for k in 0..3 {
+            let h = 1e-6;
+            let mut q = p.clone();
+            let mut rp = r;
+            rp[k] += h;
+            q.set_r(i, rp);
+            let up = potential(&q, 1.0, &fields);
+            rp[k] -= 2.0 * h;
+            q.set_r(i, rp);
+            let um = potential(&q, 1.0, &fields);
+            let grad = -(up - um) / (2.0 * h) / p.m[i];
+            max_err = max_err.max(f64::abs(grad - p.a(i)[k]));
+        }
+    }
+    println!("field gradient: {max_err}");
+    if max_err > 1e-8 {
+        println!("Error1"); exit(1);
+    }
+
+    // the method must follow a tight reference, the fields move the planet
+    // far more than the error allowed
+    let max_time = 2.0;
+    let reference = integrate(scenario(max_time, true), &mut Dopri5::new(1e-12, 1e-14), max_time);
+    let unperturbed = integrate(scenario(max_time, false), &mut Dopri5::new(1e-12, 1e-14), max_time);
+    let effect = max_distance(&reference, &unperturbed);
+    let result = integrate(scenario(1e-4, true), method, max_time);
+    let err = max_distance(&result, &reference);
+    println!("field: {err} {effect}");
+    if err > 0.01 * effect {
+        println!("Error2"); exit(1);
+    }
+    println!("Ok");
+}
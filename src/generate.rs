@@ -0,0 +1,344 @@
+use std::f64::consts::PI;
+use std::process::exit;
+
+use crate::body::{Body, System};
+use crate::elements::{Elements, state_at};
+use crate::diagnostics::measure;
+use crate::load::{read, to_text};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    // circular orbits around the central mass, radii uniform in [rmin, rmax]
+    Ring,
+    // surface density exp(-r / scale) between rmin and rmax, circular
+    // velocities from the central mass and the disk inside r
+    Disk,
+    // Plummer sphere of scale radius scale cut at rmax, no central mass
+    Plummer,
+    // planets with log-uniform a in [rmin, rmax] around the central mass,
+    // the rest are moons well inside the Hill spheres of the planets
+    Hierarchy
+}
+
+// names used by the generate subcommand
+pub const KINDS: &[(&str, Kind)] = &[
+    ("ring", Kind::Ring),
+    ("disk", Kind::Disk),
+    ("plummer", Kind::Plummer),
+    ("hierarchy", Kind::Hierarchy),
+];
+
+pub fn kind_by_name(name: &str) -> Option<Kind> {
+    KINDS.iter().find(|k| k.0 == name).map(|k| k.1)
+}
+
+#[derive(Clone, Debug)]
+pub struct Params {
+    pub n: usize,
+    pub seed: u64,
+    pub g: f64,
+    pub central: f64,
+    pub rmin: f64,
+    pub rmax: f64,
+    // disk and Plummer scale length, rmax / 3 when None
+    pub scale: Option<f64>,
+    // masses are log-uniform in [mmin, mmax]
+    pub mmin: f64,
+    pub mmax: f64
+}
+
+impl Params {
+    pub fn new() -> Params {
+        Params {
+            n : 1000,
+            seed : 1,
+            g : 1.0,
+            central : 1.0,
+            rmin : 1.0,
+            rmax : 2.0,
+            scale : None,
+            mmin : 1e-6,
+            mmax : 1e-6
+        }
+    }
+}
+
+// below this fraction of accepted samples the rejection loops would take
+// too long, or forever when it is 0
+const MIN_ACCEPTANCE: f64 = 1e-3;
+
+impl Params {
+    // the masses are log-uniform, the radii and the scale have to leave
+    // something to sample from
+    pub fn check(&self, kind: Kind) -> Result<(), String> {
+        if self.mmin.is_nan() || self.mmax.is_nan() || self.mmin <= 0.0 || self.mmax < self.mmin {
+            return Err(format!("need 0 < mmin <= mmax, got mmin {} and mmax {}", self.mmin, self.mmax));
+        }
+        // Plummer spheres have no rmin
+        let rmin = if kind == Kind::Plummer { 0.0 } else { self.rmin };
+        if rmin.is_nan() || self.rmax.is_nan() || rmin < 0.0 || self.rmax < rmin {
+            return Err(format!("need 0 <= rmin <= rmax, got rmin {} and rmax {}", self.rmin, self.rmax));
+        }
+        // the circular speed diverges at r = 0, and the hierarchy draws
+        // its semi-major axes log-uniform
+        if kind != Kind::Plummer && rmin == 0.0 {
+            return Err(String::from("rmin must be positive"));
+        }
+        let scale = self.scale.unwrap_or(self.rmax / 3.0);
+        if scale.is_nan() || scale <= 0.0 {
+            return Err(format!("the scale must be positive, got {scale}"));
+        }
+        // the fraction of the distribution between rmin and rmax
+        let kept = match kind {
+            Kind::Ring | Kind::Hierarchy => 1.0,
+            Kind::Disk => {
+                let inside = |r: f64| 1.0 - f64::exp(-r / scale) * (1.0 + r / scale);
+                inside(self.rmax) - inside(self.rmin)
+            }
+            Kind::Plummer => f64::powi(self.rmax, 3) / f64::powf(self.rmax * self.rmax + scale * scale, 1.5)
+        };
+        if kept < MIN_ACCEPTANCE {
+            return Err(format!("only {kept:.1e} of the mass lies between rmin {rmin} and rmax {} for the scale {scale}", self.rmax));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// splitmix64, the same seed gives the same file on every platform
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    fn uniform(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn range(&mut self, lo: f64, hi: f64) -> f64 {
+        lo + (hi - lo) * self.uniform()
+    }
+
+    fn log_range(&mut self, lo: f64, hi: f64) -> f64 {
+        if lo == hi {
+            return lo;
+        }
+        f64::exp(self.range(lo.ln(), hi.ln()))
+    }
+
+    // uniform on the sphere of radius r
+    fn direction(&mut self, r: f64) -> [f64; 3] {
+        let z = self.range(-1.0, 1.0);
+        let phi = self.range(0.0, 2.0 * PI);
+        let s = f64::sqrt(1.0 - z * z);
+        [r * s * phi.cos(), r * s * phi.sin(), r * z]
+    }
+}
+
+// circular orbit of radius r at angle phi in the xy plane
+fn circular(r: f64, phi: f64, mu: f64) -> ([f64; 3], [f64; 3]) {
+    let v = f64::sqrt(mu / r);
+    let (s, c) = phi.sin_cos();
+    ([r * c, r * s, 0.0], [-v * s, v * c, 0.0])
+}
+
+fn ring(data: &mut System, p: &Params, rng: &mut Rng) {
+    data.add(Body::new("Center"), [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], p.central);
+    for i in 0..p.n {
+        let m = rng.log_range(p.mmin, p.mmax);
+        let (r, v) = circular(rng.range(p.rmin, p.rmax), rng.range(0.0, 2.0 * PI), p.g * (p.central + m));
+        data.add(Body::new(&format!("B{i}")), r, v, m);
+    }
+}
+
+fn disk(data: &mut System, p: &Params, rng: &mut Rng) {
+    let scale = p.scale.unwrap_or(p.rmax / 3.0);
+    // r exp(-r / scale) is a gamma distribution, the sum of two exponentials
+    let mut radii = Vec::with_capacity(p.n);
+    while radii.len() < p.n {
+        let r = -scale * f64::ln((1.0 - rng.uniform()) * (1.0 - rng.uniform()));
+        if r >= p.rmin && r <= p.rmax {
+            radii.push(r);
+        }
+    }
+    radii.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    data.add(Body::new("Center"), [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], p.central);
+    // the disk mass inside r counts as if it were spherical
+    let mut inside = p.central;
+    for (i, r) in radii.into_iter().enumerate() {
+        let m = rng.log_range(p.mmin, p.mmax);
+        inside += m;
+        let (r, v) = circular(r, rng.range(0.0, 2.0 * PI), p.g * inside);
+        data.add(Body::new(&format!("D{i}")), r, v, m);
+    }
+}
+
+fn plummer(data: &mut System, p: &Params, rng: &mut Rng) {
+    let a = p.scale.unwrap_or(p.rmax / 3.0);
+    let masses: Vec<f64> = (0..p.n).map(|_| rng.log_range(p.mmin, p.mmax)).collect();
+    let total: f64 = masses.iter().sum();
+    for (i, m) in masses.into_iter().enumerate() {
+        // inverse of the cumulative mass r^3 / (r^2 + a^2)^(3/2)
+        let r = loop {
+            let x = rng.uniform();
+            let r = a / f64::sqrt(f64::powf(x, -2.0 / 3.0) - 1.0);
+            if r <= p.rmax { break r; }
+        };
+        // speed in units of the escape speed, distributed as q^2 (1 - q^2)^(7/2)
+        let q = loop {
+            let q = rng.uniform();
+            if 0.1 * rng.uniform() < q * q * f64::powf(1.0 - q * q, 3.5) { break q; }
+        };
+        let escape = f64::sqrt(2.0 * p.g * total) * f64::powf(r * r + a * a, -0.25);
+        data.add(Body::new(&format!("S{i}")), rng.direction(r), rng.direction(q * escape), m);
+    }
+    // at rest in the center of mass frame, O(N) unlike measure
+    let pt = &mut data.particles;
+    let mut com = [0.0; 3];
+    let mut momentum = [0.0; 3];
+    for i in 0..pt.len() {
+        let (r, v, m) = (pt.r(i), pt.v(i), pt.m[i]);
+        for k in 0..3 {
+            com[k] += m * r[k] / total;
+            momentum[k] += m * v[k];
+        }
+    }
+    for i in 0..pt.len() {
+        let (mut r, mut v) = (pt.r(i), pt.v(i));
+        for k in 0..3 {
+            r[k] -= com[k];
+            v[k] -= momentum[k] / total;
+        }
+        pt.set_r(i, r);
+        pt.set_v(i, v);
+    }
+}
+
+// random orientation and true anomaly, nearly circular and nearly coplanar
+fn orbit(a: f64, rng: &mut Rng) -> (Elements, f64) {
+    let el = Elements {
+        a : a,
+        e : rng.range(0.0, 0.1),
+        i : f64::to_radians(rng.range(0.0, 5.0)),
+        node : rng.range(0.0, 2.0 * PI),
+        peri : rng.range(0.0, 2.0 * PI),
+        mean_anomaly : 0.0,
+        period : 0.0
+    };
+    (el, rng.range(0.0, 2.0 * PI))
+}
+
+fn hierarchy(data: &mut System, p: &Params, rng: &mut Rng) {
+    data.add(Body::new("Star"), [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], p.central);
+    let planets = f64::sqrt(p.n as f64).ceil() as usize;
+    let planets = planets.min(p.n);
+    for i in 0..planets {
+        let m = rng.log_range(p.mmin, p.mmax);
+        let (el, nu) = orbit(rng.log_range(p.rmin, p.rmax), rng);
+        let (r, v) = state_at(&el, nu, p.g * (p.central + m));
+        data.add(Body::new(&format!("P{i}")), r, v, m);
+    }
+    for i in 0..p.n - planets {
+        let j = 1 + (rng.next() % planets as u64) as usize;
+        let (rp, vp, mp) = (data.particles.r(j), data.particles.v(j), data.particles.m[j]);
+        let d = f64::sqrt(rp[0] * rp[0] + rp[1] * rp[1] + rp[2] * rp[2]);
+        let hill = d * f64::cbrt(mp / (3.0 * p.central));
+        let m = 1e-3 * mp * rng.uniform();
+        let (el, nu) = orbit(hill * rng.range(0.05, 0.3), rng);
+        let (r, v) = state_at(&el, nu, p.g * (mp + m));
+        data.add(Body::new(&format!("M{i}")),
+            [rp[0] + r[0], rp[1] + r[1], rp[2] + r[2]],
+            [vp[0] + v[0], vp[1] + v[1], vp[2] + v[2]], m);
+    }
+}
+
+// n bodies of the given kind, plus the central one for all but Plummer
+pub fn generate(kind: Kind, p: &Params) -> Result<System, String> {
+    p.check(kind)?;
+    let mut data = System::new(0.0);
+    data.g = p.g;
+    let mut rng = Rng(p.seed);
+    match kind {
+        Kind::Ring => ring(&mut data, p, &mut rng),
+        Kind::Disk => disk(&mut data, p, &mut rng),
+        Kind::Plummer => plummer(&mut data, p, &mut rng),
+        Kind::Hierarchy => hierarchy(&mut data, p, &mut rng)
+    }
+    Ok(data)
+}
+
+pub fn run_test() {
+    let p = Params { n : 2000, seed : 7, rmax : 3.0, mmin : 1e-4, mmax : 1e-3, ..Params::new() };
+    for &(name, kind) in KINDS {
+        let data = generate(kind, &p).unwrap();
+        let again = generate(kind, &p).unwrap();
+        // the legacy writer and reader keep every bit
+        let mut copy = System::new(0.0);
+        if let Err(e) = read(&mut copy, name, to_text(&data).as_bytes()) {
+            println!("{e}");
+            println!("Error1"); exit(1);
+        }
+        let expected = if kind == Kind::Plummer { p.n } else { p.n + 1 };
+        let pt = &data.particles;
+        println!("{name}: {}", pt.len());
+        if pt.len() != expected || pt.x != again.particles.x || copy.particles.vy != pt.vy {
+            println!("Error2"); exit(1);
+        }
+    }
+
+    // ring bodies stay between rmin and rmax on circular orbits
+    let data = generate(Kind::Ring, &p).unwrap();
+    let pt = &data.particles;
+    for i in 1..pt.len() {
+        let (r, v) = (pt.r(i), pt.v(i));
+        let d = f64::sqrt(r[0] * r[0] + r[1] * r[1]);
+        let speed = f64::sqrt(v[0] * v[0] + v[1] * v[1]);
+        if d < p.rmin || d > p.rmax || f64::abs(speed * speed * d / (p.central + pt.m[i]) - 1.0) > 1e-14 {
+            println!("ring {i}: {d} {speed}");
+            println!("Error3"); exit(1);
+        }
+    }
+
+    // a Plummer sphere starts in virial equilibrium, 2K = -U
+    let p = Params { n : 2000, rmax : 30.0, scale : Some(1.0), mmin : 1.0, mmax : 1.0, ..Params::new() };
+    let d = measure(&generate(Kind::Plummer, &p).unwrap());
+    let virial = -2.0 * d.kinetic / d.potential;
+    let drift = d.momentum.iter().chain(&d.center_of_mass).fold(0.0, |a: f64, x| a.max(x.abs()));
+    println!("plummer virial ratio: {virial}, center of mass and momentum {drift}");
+    if f64::abs(virial - 1.0) > 0.1 || drift > 1e-10 {
+        println!("Error4"); exit(1);
+    }
+
+    // nothing to sample from is an error, not an endless loop
+    let bad = [
+        (Kind::Plummer, Params { rmax : 1e-3, scale : Some(1.0), ..Params::new() }),
+        (Kind::Disk, Params { rmin : 50.0, rmax : 60.0, scale : Some(1.0), ..Params::new() }),
+        (Kind::Ring, Params { rmin : 2.0, rmax : 1.0, ..Params::new() }),
+        (Kind::Hierarchy, Params { rmin : 0.0, ..Params::new() }),
+        (Kind::Ring, Params { rmin : 0.0, ..Params::new() }),
+        (Kind::Disk, Params { rmin : 0.0, ..Params::new() }),
+        (Kind::Ring, Params { mmin : 0.0, ..Params::new() }),
+        (Kind::Disk, Params { mmin : 1e-3, mmax : 1e-4, ..Params::new() }),
+    ];
+    for (kind, p) in bad {
+        match generate(kind, &p) {
+            Err(e) => println!("{e}"),
+            Ok(_) => {
+                println!("Error5"); exit(1);
+            }
+        }
+    }
+    println!("Ok");
+}
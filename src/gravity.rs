@@ -0,0 +1,239 @@
+use std::process::exit;
+
+use crate::body::Particles;
+use crate::barnes_hut::Octree;
+use crate::constraint::{Constraint, add_springs};
+use crate::field::{Field, add_fields};
+use crate::relativity::add_relativity;
+use crate::nongrav::{NonGrav, add_drag, add_radiation};
+
+// how the pairwise attraction is evaluated
+#[derive(Clone)]
+pub struct Forces {
+    // Barnes-Hut opening angle, 0 means direct summation
+    pub theta: f64,
+    // direct summation runs on the rayon pool when above 1,
+    // needs the parallel feature
+    pub threads: usize,
+    // Plummer softening length, 1/r^2 becomes r/(r^2+eps^2)^(3/2),
+    // keeps close encounters finite
+    pub softening: f64,
+    // per body confinement, springs act here, walls and rattle in the integrators
+    pub constraints: Vec<Constraint>,
+    // external potentials
+    pub fields: Vec<Field>,
+    // speed of light for the 1PN correction, 0 is newtonian
+    pub light_speed: f64,
+    // drag and radiation pressure
+    pub nongrav: Vec<NonGrav>
+}
+
+impl Forces {
+    pub fn new() -> Forces {
+        Forces {
+            theta : 0.0,
+            threads : 1,
+            softening : 0.0,
+            constraints : Vec::new(),
+            fields : Vec::new(),
+            light_speed : 0.0,
+            nongrav : Vec::new()
+        }
+    }
+
+    // some acceleration depends on the velocity
+    pub fn velocity_dependent(&self) -> bool {
+        self.light_speed > 0.0 || self.nongrav.iter().any(|f| f.velocity_dependent())
+    }
+}
+
+impl Default for Forces {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// total accelerations into p.ax, p.ay, p.az, fixed particles get zero
+pub fn accelerations(p: &mut Particles, g: f64, forces: &Forces) {
+    pairwise(p, g, forces);
+    add_external(p, g, forces);
+}
+
+// everything that is not the mutual attraction, on top of p.ax, p.ay, p.az
+pub fn add_external(p: &mut Particles, g: f64, forces: &Forces) {
+    add_positional(p, g, forces);
+    add_velocity_dependent(p, g, forces);
+}
+
+// external terms that depend on the positions only
+pub fn add_positional(p: &mut Particles, g: f64, forces: &Forces) {
+    add_springs(p, &forces.constraints);
+    add_fields(p, g, &forces.fields);
+    add_radiation(p, g, &forces.nongrav);
+}
+
+pub fn add_velocity_dependent(p: &mut Particles, g: f64, forces: &Forces) {
+    add_relativity(p, g, forces.light_speed);
+    add_drag(p, &forces.nongrav);
+}
+
+// iterations of implicit_kick, each one gains a factor h * |da/dv|
+const MAX_ITERATIONS: usize = 20;
+
+// v' = v + h (a + b(r, v')) where a is in p.ax, p.ay, p.az and b is the
+// velocity dependent part, solved by fixed point iteration; on return p.a
+// holds the total acceleration at (r, v')
+pub fn implicit_kick(p: &mut Particles, g: f64, forces: &Forces, h: f64) {
+    let n = p.len();
+    let (v0x, v0y, v0z) = (p.vx.clone(), p.vy.clone(), p.vz.clone());
+    let (bx, by, bz) = (p.ax.clone(), p.ay.clone(), p.az.clone());
+    add_velocity_dependent(p, g, forces);
+    for _ in 0..MAX_ITERATIONS {
+        let mut change: f64 = 0.0;
+        let mut scale: f64 = 0.0;
+        for i in 0..n {
+            let v = [v0x[i] + h * p.ax[i], v0y[i] + h * p.ay[i], v0z[i] + h * p.az[i]];
+            let old = p.v(i);
+            for k in 0..3 {
+                change = change.max(f64::abs(v[k] - old[k]));
+                scale = scale.max(f64::abs(v[k]));
+            }
+            p.set_v(i, v);
+        }
+        p.ax.clone_from(&bx);
+        p.ay.clone_from(&by);
+        p.az.clone_from(&bz);
+        add_velocity_dependent(p, g, forces);
+        if change <= 4.0 * f64::EPSILON * scale {
+            break;
+        }
+    }
+    for i in 0..n {
+        p.set_v(i, [v0x[i] + h * p.ax[i], v0y[i] + h * p.ay[i], v0z[i] + h * p.az[i]]);
+    }
+}
+
+// newtonian attraction of the particles only
+pub fn pairwise(p: &mut Particles, g: f64, forces: &Forces) {
+    let eps2 = forces.softening * forces.softening;
+    if forces.theta > 0.0 {
+        let tree = Octree::new(p);
+        let acc = tree.accelerations(p, g, forces.theta, eps2);
+        store(p, &acc);
+        return;
+    }
+    #[cfg(feature = "parallel")]
+    if forces.threads > 1 {
+        let acc = crate::parallel::direct_symmetric(p, g, eps2);
+        store(p, &acc);
+        return;
+    }
+    direct(p, g, eps2);
+}
+
+pub fn store(p: &mut Particles, acc: &[[f64; 3]]) {
+    for (i, a) in acc.iter().enumerate() {
+        p.set_a(i, *a);
+    }
+}
+
+// independent partial sums, lets the compiler keep them in one SIMD register
+const LANES: usize = 4;
+
+// sum of m[j] * (r[j] - ri) / (|r[j] - ri|^2 + eps2)^(3/2) over the given slices
+fn sum_range(x: &[f64], y: &[f64], z: &[f64], m: &[f64], ri: [f64; 3], eps2: f64) -> [f64; 3] {
+    let mut ax = [0.0; LANES];
+    let mut ay = [0.0; LANES];
+    let mut az = [0.0; LANES];
+
+    let body = x.len() - x.len() % LANES;
+    let chunks = x[..body].chunks_exact(LANES)
+        .zip(y[..body].chunks_exact(LANES))
+        .zip(z[..body].chunks_exact(LANES))
+        .zip(m[..body].chunks_exact(LANES));
+    for (((xc, yc), zc), mc) in chunks {
+        for l in 0..LANES {
+            let dx = xc[l] - ri[0];
+            let dy = yc[l] - ri[1];
+            let dz = zc[l] - ri[2];
+            let r2 = dx * dx + dy * dy + dz * dz + eps2;
+            let f = mc[l] / (r2 * r2.sqrt());
+            ax[l] += f * dx;
+            ay[l] += f * dy;
+            az[l] += f * dz;
+        }
+    }
+
+    let mut a = [
+        (ax[0] + ax[1]) + (ax[2] + ax[3]),
+        (ay[0] + ay[1]) + (ay[2] + ay[3]),
+        (az[0] + az[1]) + (az[2] + az[3])
+    ];
+    for j in body..x.len() {
+        let dx = x[j] - ri[0];
+        let dy = y[j] - ri[1];
+        let dz = z[j] - ri[2];
+        let r2 = dx * dx + dy * dy + dz * dz + eps2;
+        let f = m[j] / (r2 * r2.sqrt());
+        a[0] += f * dx;
+        a[1] += f * dy;
+        a[2] += f * dz;
+    }
+    a
+}
+
+// pairwise sum, O(N^2); the rows before and after i are summed separately
+// so the inner loops have no branch; eps2 is the squared softening length
+pub fn direct(p: &mut Particles, g: f64, eps2: f64) {
+    let n = p.len();
+
+    for i in 0..n {
+        if p.fixed[i] {
+            p.set_a(i, [0.0, 0.0, 0.0]);
+            continue;
+        }
+        let ri = p.r(i);
+        let lo = sum_range(&p.x[..i], &p.y[..i], &p.z[..i], &p.m[..i], ri, eps2);
+        let hi = sum_range(&p.x[i + 1..], &p.y[i + 1..], &p.z[i + 1..], &p.m[i + 1..], ri, eps2);
+        p.set_a(i, [g * (lo[0] + hi[0]), g * (lo[1] + hi[1]), g * (lo[2] + hi[2])]);
+    }
+}
+
+// two coincident bodies and a third one at distance 0.5: every kernel must
+// stay finite and match the softened pair force
+pub fn run_test() {
+    let eps: f64 = 0.1;
+    let mut p = Particles::new();
+    p.push([0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 1.0);
+    p.push([0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 2.0);
+    p.push([0.5, 0.0, 0.0], [0.0, 0.0, 0.0], 1.0);
+
+    let f = 0.5 / f64::powf(0.25 + eps * eps, 1.5);
+    let exact = [[f, 0.0, 0.0], [f, 0.0, 0.0], [-3.0 * f, 0.0, 0.0]];
+
+    let mut kernels = vec![("direct", Forces { softening : eps, ..Forces::new() })];
+    kernels.push(("barnes-hut", Forces { theta : 0.5, softening : eps, ..Forces::new() }));
+    #[cfg(feature = "parallel")]
+    kernels.push(("parallel", Forces { threads : 2, softening : eps, ..Forces::new() }));
+
+    for (name, forces) in &kernels {
+        let mut q = p.clone();
+        accelerations(&mut q, 1.0, forces);
+        let mut max_err: f64 = 0.0;
+        for (i, e) in exact.iter().enumerate() {
+            let a = q.a(i);
+            for k in 0..3 {
+                // keep a NaN, f64::max would drop it
+                let err = f64::abs(a[k] - e[k]);
+                if err.is_nan() || err > max_err {
+                    max_err = err;
+                }
+            }
+        }
+        println!("softening {name}: {max_err}");
+        if max_err.is_nan() || max_err >= 1e-12 {
+            println!("Error1"); exit(1);
+        }
+    }
+    println!("Ok");
+}
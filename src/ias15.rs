@@ -0,0 +1,349 @@
+use crate::body::{Particles, System};
+use crate::gravity::accelerations;
+use crate::integrator::Integrator;
+
+// Gauss-Radau spacings
+const H: [f64; 8] = [
+    0.0,
+    0.05626256053692215,
+    0.18024069173689236,
+    0.3526247171131696,
+    0.5471536263305554,
+    0.7342101772154105,
+    0.8853209468390958,
+    0.9775206135612875
+];
+
+// the step is rejected when the new step is below this fraction of the old one
+const SAFETY: f64 = 0.25;
+const MIN_EPSILON: f64 = 1e-10;
+
+// IAS15, 15th order Gauss-Radau integrator with adaptive step
+// (Everhart 1985, Rein & Spiegel 2015). data.dt is the output interval,
+// the last internal step before an output time is shortened to hit it exactly.
+// Over a step the acceleration is a polynomial in the step fraction s,
+//   a(s) = a0 + b0 s + b1 s^2 + ... + b6 s^7,
+// and b is found by predictor-corrector iterations over the Radau nodes.
+pub struct Ias15 {
+    // relative error of the b6 term that sets the step
+    pub epsilon: f64,
+    // suggested internal step
+    h: f64,
+    t: f64,
+    t_out: f64,
+    // c[j][k]: coefficients of s(s - H1)..(s - Hj) = sum c[j][k] s^(k+1)
+    c: [[f64; 7]; 7],
+    // state, 3 components per body
+    x: Vec<f64>,
+    v: Vec<f64>,
+    // compensated summation errors of x and v
+    csx: Vec<f64>,
+    csv: Vec<f64>,
+    a0: Vec<f64>,
+    b: [Vec<f64>; 7],
+    g: [Vec<f64>; 7],
+    // b predicted at the start of the last step
+    e: [Vec<f64>; 7],
+    stage: Particles
+}
+
+impl Ias15 {
+    pub fn new(epsilon: f64) -> Ias15 {
+        let mut c = [[0.0; 7]; 7];
+        c[0][0] = 1.0;
+        for j in 1..7 {
+            // multiply by (s - H[j])
+            for k in 0..7 {
+                let prev = if k > 0 { c[j - 1][k - 1] } else { 0.0 };
+                c[j][k] = prev - H[j] * c[j - 1][k];
+            }
+        }
+
+        Ias15 {
+            epsilon : epsilon,
+            h : 0.0,
+            t : 0.0,
+            t_out : 0.0,
+            c : c,
+            x : Vec::new(),
+            v : Vec::new(),
+            csx : Vec::new(),
+            csv : Vec::new(),
+            a0 : Vec::new(),
+            b : Default::default(),
+            g : Default::default(),
+            e : Default::default(),
+            stage : Particles::new()
+        }
+    }
+
+    fn acc(&mut self, data: &System, x: &[f64], v: &[f64], out: &mut [f64]) {
+        let n = self.stage.len();
+        for i in 0..n {
+            self.stage.set_r(i, [x[3 * i], x[3 * i + 1], x[3 * i + 2]]);
+            self.stage.set_v(i, [v[3 * i], v[3 * i + 1], v[3 * i + 2]]);
+        }
+        accelerations(&mut self.stage, data.g, &data.forces);
+        for i in 0..n {
+            out[3 * i..3 * i + 3].copy_from_slice(&self.stage.a(i));
+        }
+    }
+
+    // g from b by back substitution of b[k] = sum_{j >= k} c[j][k] g[j]
+    fn g_from_b(&mut self) {
+        let m = self.x.len();
+        for j in 0..m {
+            for k in (0..7).rev() {
+                let mut s = self.b[k][j];
+                for l in k + 1..7 {
+                    s -= self.c[l][k] * self.g[l][j];
+                }
+                self.g[k][j] = s;
+            }
+        }
+    }
+
+    // increments of position and velocity at the fraction s of a step of length h
+    fn predict(&self, s: f64, h: f64, x: &mut [f64], v: &mut [f64]) {
+        let b = &self.b;
+        for j in 0..self.x.len() {
+            let xs = self.a0[j] / 2.0 + s * (b[0][j] / 6.0 + s * (b[1][j] / 12.0 + s * (b[2][j] / 20.0
+                + s * (b[3][j] / 30.0 + s * (b[4][j] / 42.0 + s * (b[5][j] / 56.0 + s * b[6][j] / 72.0))))));
+            let vs = self.a0[j] + s * (b[0][j] / 2.0 + s * (b[1][j] / 3.0 + s * (b[2][j] / 4.0
+                + s * (b[3][j] / 5.0 + s * (b[4][j] / 6.0 + s * (b[5][j] / 7.0 + s * b[6][j] / 8.0))))));
+            x[j] = s * h * self.v[j] + s * s * h * h * xs;
+            v[j] = s * h * vs;
+        }
+    }
+
+    // moves the polynomial to a step of length ratio * h, e keeps the
+    // prediction so its error can be added back on the next step
+    fn predict_next(&mut self, ratio: f64) {
+        let m = self.x.len();
+        if ratio > 20.0 {
+            for k in 0..7 {
+                self.e[k].iter_mut().for_each(|x| *x = 0.0);
+                self.b[k].iter_mut().for_each(|x| *x = 0.0);
+            }
+            return;
+        }
+        let q1 = ratio;
+        let q2 = q1 * q1;
+        let q3 = q2 * q1;
+        let q4 = q2 * q2;
+        let q5 = q4 * q1;
+        let q6 = q3 * q3;
+        let q7 = q6 * q1;
+        for j in 0..m {
+            let b: [f64; 7] = std::array::from_fn(|k| self.b[k][j]);
+            let be: [f64; 7] = std::array::from_fn(|k| self.b[k][j] - self.e[k][j]);
+            let e = [
+                q1 * (b[6] * 7.0 + b[5] * 6.0 + b[4] * 5.0 + b[3] * 4.0 + b[2] * 3.0 + b[1] * 2.0 + b[0]),
+                q2 * (b[6] * 21.0 + b[5] * 15.0 + b[4] * 10.0 + b[3] * 6.0 + b[2] * 3.0 + b[1]),
+                q3 * (b[6] * 35.0 + b[5] * 20.0 + b[4] * 10.0 + b[3] * 4.0 + b[2]),
+                q4 * (b[6] * 35.0 + b[5] * 15.0 + b[4] * 5.0 + b[3]),
+                q5 * (b[6] * 21.0 + b[5] * 6.0 + b[4]),
+                q6 * (b[6] * 7.0 + b[5]),
+                q7 * b[6]
+            ];
+            for k in 0..7 {
+                self.e[k][j] = e[k];
+                self.b[k][j] = e[k] + be[k];
+            }
+        }
+    }
+
+    // one accepted internal step no longer than max_h, returns its length
+    fn advance(&mut self, data: &System, max_h: f64) -> f64 {
+        let m = self.x.len();
+        let mut xs = vec![0.0; m];
+        let mut vs = vec![0.0; m];
+        let mut at = vec![0.0; m];
+
+        // the polynomial was predicted for self.h
+        let mut h = self.h;
+        if max_h < h {
+            self.rescale(max_h / h);
+            h = max_h;
+        }
+
+        let x = self.x.clone();
+        let v = self.v.clone();
+        let mut a0 = std::mem::take(&mut self.a0);
+        self.acc(data, &x, &v, &mut a0);
+        self.a0 = a0;
+
+        loop {
+            self.g_from_b();
+
+            let mut pc_error = f64::MAX;
+            let mut pc_error_last = 2.0;
+            for iter in 0..12 {
+                if pc_error < 1e-16 {
+                    break;
+                }
+                if iter > 2 && pc_error_last <= pc_error {
+                    break;
+                }
+                pc_error_last = pc_error;
+
+                let mut max_db6: f64 = 0.0;
+                let mut max_a: f64 = 0.0;
+                for n in 1..8 {
+                    self.predict(H[n], h, &mut xs, &mut vs);
+                    for (xs, x) in xs.iter_mut().zip(x.iter()) {
+                        *xs += x;
+                    }
+                    for (vs, v) in vs.iter_mut().zip(v.iter()) {
+                        *vs += v;
+                    }
+                    self.acc(data, &xs, &vs, &mut at);
+                    for (j, &aj) in at.iter().enumerate() {
+                        // divided differences give g[n - 1]
+                        let mut gk = (aj - self.a0[j]) / (H[n] - H[0]);
+                        for (gl, hl) in self.g[..n - 1].iter().zip(&H[1..n]) {
+                            gk = (gk - gl[j]) / (H[n] - hl);
+                        }
+                        let d = gk - self.g[n - 1][j];
+                        self.g[n - 1][j] = gk;
+                        for k in 0..n {
+                            self.b[k][j] += self.c[n - 1][k] * d;
+                        }
+                        if n == 7 {
+                            max_db6 = max_db6.max(d.abs());
+                            max_a = max_a.max(aj.abs());
+                        }
+                    }
+                }
+                pc_error = if max_a > 0.0 { max_db6 / max_a } else { 0.0 };
+            }
+
+            let mut max_b6: f64 = 0.0;
+            let mut max_a: f64 = 0.0;
+            for (b6, a) in self.b[6].iter().zip(at.iter()) {
+                max_b6 = max_b6.max(b6.abs());
+                max_a = max_a.max(a.abs());
+            }
+            let error = max_b6 / max_a;
+            let h_new = if error.is_normal() {
+                h * f64::powf(self.epsilon / error, 1.0 / 7.0)
+            } else {
+                h / SAFETY
+            };
+
+            if h_new.abs() < SAFETY * h.abs() && h_new > 0.0 {
+                // reject, retry with the smaller step
+                self.rescale(h_new / h);
+                h = h_new;
+                continue;
+            }
+
+            // accept
+            self.predict(1.0, h, &mut xs, &mut vs);
+            for j in 0..m {
+                add_cs(&mut self.x[j], &mut self.csx[j], xs[j]);
+                add_cs(&mut self.v[j], &mut self.csv[j], vs[j]);
+            }
+
+            let h_next = f64::min(h_new, h / SAFETY);
+            self.predict_next(h_next / h);
+            self.h = h_next;
+            return h;
+        }
+    }
+
+    // the same polynomial expressed for a step ratio times longer
+    fn rescale(&mut self, ratio: f64) {
+        let mut q = ratio;
+        for k in 0..7 {
+            self.b[k].iter_mut().for_each(|x| *x *= q);
+            self.e[k].iter_mut().for_each(|x| *x *= q);
+            q *= ratio;
+        }
+    }
+}
+
+impl Integrator for Ias15 {
+    fn name(&self) -> &'static str {
+        "ias15"
+    }
+
+    fn order(&self) -> u32 {
+        15
+    }
+
+    fn adaptive(&self) -> bool {
+        true
+    }
+
+    // the error estimate is relative, atol is not used
+    fn set_tolerance(&mut self, rtol: f64, _atol: f64) {
+        // b6 carries roundoff noise of about 1e-12 relative to the acceleration,
+        // a smaller epsilon would shrink the step without bound
+        if rtol < MIN_EPSILON {
+            eprintln!("ias15: rtol {rtol:e} is below the roundoff limit, using {MIN_EPSILON:e}");
+        }
+        self.epsilon = f64::max(rtol, MIN_EPSILON);
+    }
+
+    fn init(&mut self, data: &mut System) {
+        let n = data.particles.len();
+        let m = 3 * n;
+        self.stage.clone_from(&data.particles);
+        self.x = vec![0.0; m];
+        self.v = vec![0.0; m];
+        for i in 0..n {
+            self.x[3 * i..3 * i + 3].copy_from_slice(&data.particles.r(i));
+            // fixed bodies are integrated at rest, their reported velocity stays as it is
+            if !data.particles.fixed[i] {
+                self.v[3 * i..3 * i + 3].copy_from_slice(&data.particles.v(i));
+            }
+        }
+        self.csx = vec![0.0; m];
+        self.csv = vec![0.0; m];
+        self.a0 = vec![0.0; m];
+        for k in 0..7 {
+            self.b[k] = vec![0.0; m];
+            self.g[k] = vec![0.0; m];
+            self.e[k] = vec![0.0; m];
+        }
+        self.t = 0.0;
+        self.t_out = 0.0;
+        self.h = data.dt;
+    }
+
+    fn step(&mut self, data: &mut System) {
+        self.t_out += data.dt;
+        while self.t < self.t_out {
+            let left = self.t_out - self.t;
+            let suggested = self.h;
+            let done = self.advance(data, left);
+            if done < left {
+                self.t += done;
+            } else {
+                self.t = self.t_out;
+                // a step shortened to hit the output time keeps the old suggestion
+                if suggested > self.h {
+                    self.rescale(suggested / self.h);
+                    self.h = suggested;
+                }
+            }
+        }
+
+        let p = &mut data.particles;
+        for i in 0..p.len() {
+            if p.fixed[i] { continue; }
+            p.set_r(i, [self.x[3 * i], self.x[3 * i + 1], self.x[3 * i + 2]]);
+            p.set_v(i, [self.v[3 * i], self.v[3 * i + 1], self.v[3 * i + 2]]);
+            p.set_a(i, [self.a0[3 * i], self.a0[3 * i + 1], self.a0[3 * i + 2]]);
+        }
+    }
+}
+
+// Kahan summation, cs keeps the lost low order bits of sum
+fn add_cs(sum: &mut f64, cs: &mut f64, inp: f64) {
+    let y = inp - *cs;
+    let t = *sum + y;
+    *cs = (t - *sum) - y;
+    *sum = t;
+}
@@ -0,0 +1,63 @@
+use crate::body::System;
+use crate::euler::Euler;
+use crate::verlet::Verlet;
+use crate::rk4::Rk4;
+use crate::dopri5::Dopri5;
+use crate::symplectic::{Composition, ForestRuth};
+use crate::wh::WisdomHolman;
+use crate::ias15::Ias15;
+
+pub trait Integrator {
+    // name used by --method
+    fn name(&self) -> &'static str;
+    // order of the global error
+    fn order(&self) -> u32;
+    // called once before the first step
+    fn init(&mut self, data: &mut System);
+    // advances the system by data.dt
+    fn step(&mut self, data: &mut System);
+    // adaptive methods pick their own internal step and use data.dt
+    // only as the output interval
+    fn adaptive(&self) -> bool {
+        false
+    }
+    // error tolerances of adaptive methods
+    fn set_tolerance(&mut self, _rtol: f64, _atol: f64) { }
+    // methods that solve the unperturbed two-body problem exactly
+    fn kepler_exact(&self) -> bool {
+        false
+    }
+    // methods that apply walls and rattle constraints inside the step,
+    // the others get their state projected after every step
+    fn handles_constraints(&self) -> bool {
+        false
+    }
+}
+
+// (name, title) of every method, in the order shown in the GUI
+pub const METHODS: &[(&str, &str)] = &[
+    ("euler", "Euler"),
+    ("verlet", "Verlet"),
+    ("rk4", "RK4"),
+    ("dopri5", "Dormand-Prince 5(4)"),
+    ("yoshida4", "Yoshida 4"),
+    ("yoshida6", "Yoshida 6"),
+    ("forest-ruth", "Forest-Ruth"),
+    ("wh", "Wisdom-Holman"),
+    ("ias15", "IAS15"),
+];
+
+pub fn by_name(name: &str) -> Option<Box<dyn Integrator>> {
+    match name {
+        "euler" => Some(Box::new(Euler)),
+        "verlet" => Some(Box::new(Verlet)),
+        "rk4" => Some(Box::new(Rk4::new())),
+        "dopri5" => Some(Box::new(Dopri5::new(1e-9, 1e-12))),
+        "yoshida4" => Some(Box::new(Composition::yoshida4())),
+        "yoshida6" => Some(Box::new(Composition::yoshida6())),
+        "forest-ruth" => Some(Box::new(ForestRuth)),
+        "wh" => Some(Box::new(WisdomHolman::new())),
+        "ias15" => Some(Box::new(Ias15::new(1e-9))),
+        _ => None
+    }
+}
@@ -0,0 +1,230 @@
+use std::process::exit;
+
+use crate::body::{Body, System};
+use crate::integrator::Integrator;
+use crate::dopri5::Dopri5;
+
+// circular orbit of a light body around a fixed heavy one,
+// returns max deviation from the initial radius
+pub fn kepler(dt: f64, method: &mut dyn Integrator) -> f64 {
+    let g = 1.0;
+    let mm = 1e5;
+
+    let mut data = System::new(dt);
+    data.g = g;
+    data.add(Body::new("b1"), [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], mm);
+    data.add(Body::new("b2"), [0.0, 1.0, 0.0], [f64::sqrt(g * mm), 0.0, 0.0], 1.0);
+    data.particles.fixed[0] = true;
+
+    let mut max_err = 0.0;
+    let max_time = 0.1;
+    let mut t = 0.0;
+
+    method.init(&mut data);
+
+    while t < max_time {
+        method.step(&mut data);
+
+        let r1 = data.particles.r(1);
+        let r: f64 = r1.iter().map(|x| x * x).sum();
+        let r = f64::sqrt(r);
+        let err = f64::abs(r - 1.0);
+        if max_err < err {
+            max_err = err;
+        }
+        t += dt;
+    }
+
+    return max_err;
+}
+
+pub fn run_test(method: &mut dyn Integrator) {
+    run_fixed_test(method);
+    if method.kepler_exact() {
+        run_exact_test(method); return;
+    }
+    if method.adaptive() {
+        run_adaptive_test(method); return;
+    }
+    let err1 = kepler(0.001, method);
+    let err2 = kepler(0.0001, method);
+    let err3 = kepler(0.00001, method);
+    println!("{err1} {err2} {err3}");
+    if err1 / 10.0 < err2 {
+        println!("Error1"); exit(1);
+    }
+    if err1 / 100.0 < err3 {
+        println!("Error2"); exit(1);
+    }
+    if method.order() > 2 {
+        run_order_test(method);
+    }
+    println!("Ok");
+}
+
+// fixed bodies keep position and velocity even when the file gives them a velocity
+fn run_fixed_test(method: &mut dyn Integrator) {
+    let mut data = hierarchical(0.01);
+    data.particles.fixed[0] = true;
+    data.particles.fixed[2] = true;
+    data.particles.set_v(0, [0.3, 0.0, 0.0]);
+    let initial = data.particles.clone();
+    method.init(&mut data);
+    for _ in 0..100 {
+        method.step(&mut data);
+    }
+    let p = &data.particles;
+    for i in [0, 2] {
+        if p.r(i) != initial.r(i) || p.v(i) != initial.v(i) {
+            println!("fixed {i}: {:?} {:?}", p.r(i), p.v(i));
+            println!("Error0"); exit(1);
+        }
+    }
+    if p.r(1) == initial.r(1) {
+        println!("Error0"); exit(1);
+    }
+}
+
+// halving dt must reduce the error by about 2^order
+fn run_order_test(method: &mut dyn Integrator) {
+    let order = method.order() as f64;
+    let err1 = kepler(0.0004, method);
+    let err2 = kepler(0.0002, method);
+    let err3 = kepler(0.0001, method);
+    let p1 = f64::log2(err1 / err2);
+    let p2 = f64::log2(err2 / err3);
+    println!("order {p1} {p2}");
+    if p1 < order - 0.5 || p2 < order - 0.5 {
+        println!("Error3"); exit(1);
+    }
+}
+
+// star with two planets on nearby circular orbits
+fn hierarchical(dt: f64) -> System {
+    let mut data = System::new(dt);
+    data.add(Body::new("star"), [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 1.0);
+    data.add(Body::new("p1"), [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], 1e-3);
+    data.add(Body::new("p2"), [0.0, 1.6, 0.0], [-f64::sqrt(1.0 / 1.6), 0.0, 0.0], 1e-3);
+    data
+}
+
+// max position error at t = max_time against a tight dopri5 solution
+fn hierarchical_error(dt: f64, method: &mut dyn Integrator) -> f64 {
+    let max_time = 10.0;
+
+    let mut reference = hierarchical(max_time);
+    let mut dopri = Dopri5::new(1e-13, 1e-15);
+    dopri.init(&mut reference);
+    dopri.step(&mut reference);
+
+    let mut data = hierarchical(dt);
+    let steps = (max_time / dt).round() as usize;
+    method.init(&mut data);
+    for _ in 0..steps {
+        method.step(&mut data);
+    }
+
+    let mut max_err = 0.0;
+    for i in 0..data.particles.len() {
+        let (b, r) = (data.particles.r(i), reference.particles.r(i));
+        for k in 0..3 {
+            let err = f64::abs(b[k] - r[k]);
+            if max_err < err {
+                max_err = err;
+            }
+        }
+    }
+    max_err
+}
+
+// unperturbed orbits must be exact to roundoff at any dt, the error of a
+// perturbed system must converge with the order of the method
+fn run_exact_test(method: &mut dyn Integrator) {
+    let err1 = kepler(0.001, method);
+    let err2 = kepler(0.0001, method);
+    let err3 = kepler(0.00001, method);
+    println!("{err1} {err2} {err3}");
+    if err1 > 1e-9 || err2 > 1e-9 || err3 > 1e-9 {
+        println!("Error1"); exit(1);
+    }
+
+    let order = method.order() as f64;
+    let err1 = hierarchical_error(0.04, method);
+    let err2 = hierarchical_error(0.02, method);
+    let err3 = hierarchical_error(0.01, method);
+    let p1 = f64::log2(err1 / err2);
+    let p2 = f64::log2(err2 / err3);
+    println!("{err1} {err2} {err3}");
+    println!("order {p1} {p2}");
+    if p1 < order - 0.5 || p2 < order - 0.5 {
+        println!("Error2"); exit(1);
+    }
+    println!("Ok");
+}
+
+fn energy(data: &System) -> f64 {
+    let p = &data.particles;
+    let n = p.len();
+    let mut e = 0.0;
+    for i in 0..n {
+        let (r1, v1) = (p.r(i), p.v(i));
+        let v2 = v1[0] * v1[0] + v1[1] * v1[1] + v1[2] * v1[2];
+        e += 0.5 * p.m[i] * v2;
+        for j in i + 1..n {
+            let r2 = p.r(j);
+            let mut r = 0.0;
+            for k in 0..3 {
+                r += (r1[k] - r2[k]) * (r1[k] - r2[k]);
+            }
+            e -= data.g * p.m[i] * p.m[j] / f64::sqrt(r);
+        }
+    }
+    e
+}
+
+// 3bodies.txt: the light third body passes close to the second one,
+// returns the max relative energy error
+fn encounter(method: &mut dyn Integrator) -> f64 {
+    let mut data = System::new(0.001);
+    data.add(Body::new("Body1"), [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 1e5);
+    data.add(Body::new("Body2"), [0.0, 1.0, 0.0], [316.22, 0.0, 0.0], 1e4);
+    data.add(Body::new("Body3"), [0.0, 1.1, 0.0], [-0.007766, 0.0, 0.0], 0.00001);
+
+    let e0 = energy(&data);
+    let mut max_err = 0.0;
+    method.init(&mut data);
+    for _ in 0..200 {
+        method.step(&mut data);
+        let err = f64::abs(energy(&data) / e0 - 1.0);
+        if max_err < err {
+            max_err = err;
+        }
+    }
+    max_err
+}
+
+// the error must follow the tolerance until it reaches roundoff,
+// energy must be kept through a close encounter
+fn run_adaptive_test(method: &mut dyn Integrator) {
+    let roundoff = 1e-12;
+    method.set_tolerance(1e-6, 1e-9);
+    let err1 = kepler(0.001, method);
+    method.set_tolerance(1e-8, 1e-11);
+    let err2 = kepler(0.001, method);
+    method.set_tolerance(1e-10, 1e-13);
+    let err3 = kepler(0.001, method);
+    println!("{err1} {err2} {err3}");
+    if err1 / 10.0 < err2 && err2 > roundoff {
+        println!("Error1"); exit(1);
+    }
+    if err1 / 100.0 < err3 && err3 > roundoff {
+        println!("Error2"); exit(1);
+    }
+
+    let err = encounter(method);
+    println!("energy {err}");
+    if err > 1e-11 {
+        println!("Error3"); exit(1);
+    }
+    println!("Ok");
+}
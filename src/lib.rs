@@ -0,0 +1,56 @@
+pub mod body;
+pub mod gravity;
+pub mod collision;
+pub mod constraint;
+pub mod field;
+pub mod relativity;
+pub mod nongrav;
+pub mod diagnostics;
+pub mod elements;
+pub mod units;
+pub mod barnes_hut;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod load;
+pub mod scenario;
+pub mod generate;
+pub mod output;
+pub mod euler;
+pub mod verlet;
+pub mod rk4;
+pub mod dopri5;
+pub mod symplectic;
+pub mod wh;
+pub mod ias15;
+pub mod integrator;
+pub mod kepler;
+pub mod bench;
+
+pub use body::{Body, System};
+pub use integrator::Integrator;
+
+use output::{Columns, print_header, print, print_event};
+use collision::collide;
+use constraint::enforce;
+
+pub fn solve(data: &mut System, max_time: f64, method: &mut dyn Integrator, columns: &Columns) {
+    let mut t = 0.0;
+    print_header(data, columns);
+    print(data, t, columns);
+    method.init(data);
+    while t < max_time {
+        method.step(data);
+        t += data.dt;
+        let mut changed = !method.handles_constraints() && enforce(data);
+        let events = collide(data);
+        for event in &events {
+            print_event(event, t, columns);
+        }
+        changed = changed || !events.is_empty();
+        // the integrators keep their own copy of the state
+        if changed {
+            method.init(data);
+        }
+        print(data, t, columns);
+    }
+}
@@ -0,0 +1,523 @@
+use std::fmt;
+use std::process::exit;
+use std::io::{BufReader, BufRead};
+use std::fs::File;
+
+use crate::body::{Body, System};
+use crate::constraint::{Constraint, Kind};
+use crate::field::Field;
+use crate::nongrav::NonGrav;
+use crate::elements::{Elements, conic_error, state_at};
+use crate::scenario::Scenario;
+use crate::units::{Scale, Units, LENGTHS, MASSES};
+
+/*
+  legacy file format, see scenario.rs for the TOML and JSON one:
+  G [softening [c]]
+  c is the speed of light, enables the 1PN correction
+  N
+  Body1 r0 r1 r2 v0 v1 v2 Mass [fixed]
+  Body2 r0 r1 r2 v0 v1 v2 Mass [fixed]
+  ...
+  BodyN r0 r1 r2 v0 v1 v2 Mass [fixed]
+  a body marked fixed feels no force and does not move; instead of r and v
+  a body can be given by orbital elements around a body listed before it:
+  Name orbit Parent a e i node peri nu Mass [fixed]
+  angles in degrees, nu is the true anomaly; a > 0 and e < 1 on an ellipse,
+  a < 0 and e > 1 on a hyperbola
+  optional trailers, one per body:
+  i color min_rad max_rad rad [radius]
+  rad is the size on screen, radius the physical size used for collisions,
+  min_rad/max_rad > 0 put reflecting walls around the origin
+  optional constraints, any number, bounds <= 0 are not enforced:
+  wall i cx cy cz min_rad max_rad
+  spring i cx cy cz min_rad max_rad k
+  rattle i cx cy cz rad
+  optional external fields:
+  j2 i J2 radius [ax ay az]    oblateness of body i, the axis defaults to z
+  uniform gx gy gz
+  harmonic cx cy cz k          a = -k (r - c)
+  optional non gravitational forces:
+  drag i linear|quadratic k [j]   against the velocity relative to body j
+  radiation i beta j              pressure of the light of body j
+  optional physical units, see units.rs for the names:
+  units length mass               au earth, km 1e20 ...; the unit of time
+                                  follows from G
+  empty lines are skipped, anything else is an error
+ */
+
+#[derive(Debug)]
+pub enum LoadErrorKind {
+    Io(std::io::Error),
+    // what was expected and the token found instead
+    BadValue(&'static str, String),
+    Missing(&'static str),
+    Unexpected(String),
+    TooFewBodies(usize, usize),
+    // index and number of bodies
+    BadIndex(usize, usize),
+    UnknownBody(String),
+    UnknownLine(String),
+    // TOML or JSON
+    Syntax(String)
+}
+
+// line and column start at 1, 0 when the error is about the whole file
+#[derive(Debug)]
+pub struct LoadError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub kind: LoadErrorKind
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if self.line > 0 {
+            write!(f, ":{}:{}", self.line, self.column)?;
+        }
+        match &self.kind {
+            LoadErrorKind::Io(e) => write!(f, ": {e}"),
+            LoadErrorKind::BadValue(what, token) => write!(f, ": bad {what} '{token}'"),
+            LoadErrorKind::Missing(what) => write!(f, ": missing {what}"),
+            LoadErrorKind::Unexpected(token) => write!(f, ": unexpected '{token}'"),
+            LoadErrorKind::TooFewBodies(declared, found) => write!(f, ": {declared} bodies declared, {found} found"),
+            LoadErrorKind::BadIndex(i, n) => write!(f, ": body index {i} out of range, there are {n} bodies"),
+            LoadErrorKind::UnknownBody(name) => write!(f, ": unknown body '{name}'"),
+            LoadErrorKind::UnknownLine(token) => write!(f, ": unknown line '{token}'"),
+            LoadErrorKind::Syntax(message) => write!(f, ": {message}")
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+// the whitespace separated tokens of one line with their columns
+struct Fields<'a> {
+    file: &'a str,
+    line: usize,
+    end: usize,
+    tokens: Vec<(usize, &'a str)>,
+    pos: usize
+}
+
+impl<'a> Fields<'a> {
+    fn new(file: &'a str, line: usize, text: &'a str) -> Fields<'a> {
+        let mut tokens = Vec::new();
+        let mut start = None;
+        let mut column = 0;
+        for (c, (k, ch)) in text.char_indices().enumerate() {
+            column = c + 1;
+            match (start, ch.is_whitespace()) {
+                (None, false) => start = Some((column, k)),
+                (Some((col, s)), true) => {
+                    tokens.push((col, &text[s..k]));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some((col, s)) = start {
+            tokens.push((col, &text[s..]));
+        }
+        Fields { file : file, line : line, end : column + 1, tokens : tokens, pos : 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    fn error(&self, column: usize, kind: LoadErrorKind) -> LoadError {
+        LoadError { file : String::from(self.file), line : self.line, column : column, kind : kind }
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|t| t.1)
+    }
+
+    fn next(&mut self, what: &'static str) -> Result<(usize, &'a str), LoadError> {
+        match self.tokens.get(self.pos) {
+            Some(&t) => {
+                self.pos += 1;
+                Ok(t)
+            }
+            None => Err(self.error(self.end, LoadErrorKind::Missing(what)))
+        }
+    }
+
+    fn word(&mut self, what: &'static str) -> Result<&'a str, LoadError> {
+        Ok(self.next(what)?.1)
+    }
+
+    fn number(&mut self, what: &'static str) -> Result<f64, LoadError> {
+        let (column, token) = self.next(what)?;
+        token.parse::<f64>().map_err(|_| self.error(column, LoadErrorKind::BadValue(what, String::from(token))))
+    }
+
+    fn vector(&mut self, what: &'static str) -> Result<[f64; 3], LoadError> {
+        Ok([self.number(what)?, self.number(what)?, self.number(what)?])
+    }
+
+    // index of one of the n bodies
+    fn index(&mut self, what: &'static str, n: usize) -> Result<usize, LoadError> {
+        let (column, token) = self.next(what)?;
+        let i = token.parse::<usize>().map_err(|_| self.error(column, LoadErrorKind::BadValue(what, String::from(token))))?;
+        if i >= n {
+            return Err(self.error(column, LoadErrorKind::BadIndex(i, n)));
+        }
+        Ok(i)
+    }
+
+    // a unit by name or in SI units
+    // BadValue at the token with index k
+    fn bad(&self, k: usize, what: &'static str) -> LoadError {
+        let (column, token) = self.tokens[k];
+        self.error(column, LoadErrorKind::BadValue(what, String::from(token)))
+    }
+
+    fn scale(&mut self, what: &'static str, table: &[(&str, f64)]) -> Result<f64, LoadError> {
+        let (column, token) = self.next(what)?;
+        Scale::parse(token).value(table).ok_or_else(|| self.error(column, LoadErrorKind::BadValue(what, String::from(token))))
+    }
+
+    // the optional trailing "fixed"
+    fn fixed(&mut self) -> Result<bool, LoadError> {
+        if self.peek() == Some("fixed") {
+            self.pos += 1;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn finish(&self) -> Result<(), LoadError> {
+        match self.tokens.get(self.pos) {
+            Some(&(column, token)) => Err(self.error(column, LoadErrorKind::Unexpected(String::from(token)))),
+            None => Ok(())
+        }
+    }
+}
+
+// run parameters a scenario file may set, the command line overrides them
+#[derive(Clone, Default, Debug)]
+pub struct Settings {
+    pub dt: Option<f64>,
+    pub max_time: Option<f64>,
+    pub method: Option<String>
+}
+
+// .toml and .json are scenarios, anything else the format above
+pub fn load(data: &mut System, file_name: &str) -> Result<Settings, LoadError> {
+    let io = |e| LoadError { file : String::from(file_name), line : 0, column : 0, kind : LoadErrorKind::Io(e) };
+    if file_name.ends_with(".toml") || file_name.ends_with(".json") {
+        let text = std::fs::read_to_string(file_name).map_err(io)?;
+        let scenario = if file_name.ends_with(".toml") {
+            Scenario::from_toml(file_name, &text)?
+        } else {
+            Scenario::from_json(file_name, &text)?
+        };
+        return scenario.apply(data, file_name);
+    }
+    let file = File::open(file_name).map_err(io)?;
+    read(data, file_name, BufReader::new(file))?;
+    Ok(Settings::default())
+}
+
+// the system in the format above; walls are written as wall lines
+// and run parameters are lost
+pub fn to_text(data: &System) -> String {
+    let mut s = String::new();
+    let f = &data.forces;
+    s += &format!("{} {} {}\n{}\n", data.g, f.softening, f.light_speed, data.bodies.len());
+    let p = &data.particles;
+    for (i, b) in data.bodies.iter().enumerate() {
+        let (r, v) = (p.r(i), p.v(i));
+        s += &format!("{} {} {} {} {} {} {} {}", b.name, r[0], r[1], r[2], v[0], v[1], v[2], p.m[i]);
+        s += if p.fixed[i] { " fixed\n" } else { "\n" };
+    }
+    for (i, b) in data.bodies.iter().enumerate() {
+        s += &format!("{i} {} 0 0 {} {}\n", b.color, b.rad, p.radius[i]);
+    }
+    for c in &f.constraints {
+        let [cx, cy, cz] = c.center;
+        s += &match c.kind {
+            Kind::Wall => format!("wall {} {cx} {cy} {cz} {} {}\n", c.body, c.min_rad, c.max_rad),
+            Kind::Spring(k) => format!("spring {} {cx} {cy} {cz} {} {} {k}\n", c.body, c.min_rad, c.max_rad),
+            Kind::Rattle => format!("rattle {} {cx} {cy} {cz} {}\n", c.body, c.max_rad)
+        };
+    }
+    for field in &f.fields {
+        s += &match *field {
+            Field::J2 { body, j2, radius, axis } => format!("j2 {body} {j2} {radius} {} {} {}\n", axis[0], axis[1], axis[2]),
+            Field::Uniform { g } => format!("uniform {} {} {}\n", g[0], g[1], g[2]),
+            Field::Harmonic { center, k } => format!("harmonic {} {} {} {k}\n", center[0], center[1], center[2])
+        };
+    }
+    for force in &f.nongrav {
+        s += &match *force {
+            NonGrav::Drag { body, k, quadratic, medium } => {
+                let law = if quadratic { "quadratic" } else { "linear" };
+                match medium {
+                    Some(j) => format!("drag {body} {law} {k} {j}\n"),
+                    None => format!("drag {body} {law} {k}\n")
+                }
+            }
+            NonGrav::Radiation { body, beta, source } => format!("radiation {body} {beta} {source}\n")
+        };
+    }
+    if let Some(u) = &data.units {
+        s += &format!("units {} {}\n", u.length, u.mass);
+    }
+    s
+}
+
+// the file format above from any reader, file_name is only used in errors
+pub fn read(data: &mut System, file_name: &str, reader: impl BufRead) -> Result<(), LoadError> {
+    let mut lines = Vec::new();
+    for (k, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| LoadError {
+            file : String::from(file_name), line : k + 1, column : 1, kind : LoadErrorKind::Io(e)
+        })?;
+        lines.push(line);
+    }
+    let mut lines = lines.iter().enumerate()
+        .map(|(k, text)| Fields::new(file_name, k + 1, text))
+        .filter(|f| !f.is_empty());
+    let eof = |what| LoadError { file : String::from(file_name), line : 0, column : 0, kind : LoadErrorKind::Missing(what) };
+
+    let mut header = lines.next().ok_or_else(|| eof("G"))?;
+    data.g = header.number("G")?;
+    if header.peek().is_some() {
+        data.forces.softening = header.number("softening")?;
+    }
+    if header.peek().is_some() {
+        data.forces.light_speed = header.number("the speed of light")?;
+    }
+    header.finish()?;
+
+    let mut count = lines.next().ok_or_else(|| eof("the number of bodies"))?;
+    let (column, token) = count.next("the number of bodies")?;
+    let nbodies = token.parse::<usize>()
+        .map_err(|_| count.error(column, LoadErrorKind::BadValue("the number of bodies", String::from(token))))?;
+    count.finish()?;
+    data.bodies.reserve(nbodies);
+
+    let mut last = count.line;
+    for _ in 0..nbodies {
+        let mut f = match lines.next() {
+            Some(f) => f,
+            None => return Err(LoadError {
+                file : String::from(file_name), line : last + 1, column : 1,
+                kind : LoadErrorKind::TooFewBodies(nbodies, data.bodies.len())
+            })
+        };
+        last = f.line;
+        let name = f.word("name")?;
+        if f.peek() == Some("orbit") {
+            f.pos += 1;
+            let (column, parent) = f.next("parent")?;
+            let j = data.bodies.iter().position(|b| b.name == parent)
+                .ok_or_else(|| f.error(column, LoadErrorKind::UnknownBody(String::from(parent))))?;
+            let at = f.pos;
+            let a = f.number("a")?;
+            let e = f.number("e")?;
+            if let Some((k, what)) = conic_error(a, e) {
+                return Err(f.bad(at + k, what));
+            }
+            let inc = f.number("i")?;
+            let node = f.number("node")?;
+            let peri = f.number("peri")?;
+            let nu = f.number("nu")?;
+            let m = f.number("mass")?;
+            let fixed = f.fixed()?;
+            f.finish()?;
+            let el = Elements {
+                a : a, e : e, i : f64::to_radians(inc), node : f64::to_radians(node), peri : f64::to_radians(peri),
+                mean_anomaly : 0.0, period : 0.0
+            };
+            let (r, v) = state_at(&el, f64::to_radians(nu), data.g * (data.particles.m[j] + m));
+            let (rp, vp) = (data.particles.r(j), data.particles.v(j));
+            data.add(Body::new(name),
+                [rp[0] + r[0], rp[1] + r[1], rp[2] + r[2]],
+                [vp[0] + v[0], vp[1] + v[1], vp[2] + v[2]], m);
+            let i = data.particles.len() - 1;
+            data.particles.fixed[i] = fixed;
+        } else {
+            let r = f.vector("position")?;
+            let v = f.vector("velocity")?;
+            let m = f.number("mass")?;
+            let fixed = f.fixed()?;
+            f.finish()?;
+            data.add(Body::new(name), r, v, m);
+            let i = data.particles.len() - 1;
+            data.particles.fixed[i] = fixed;
+        }
+    }
+
+    let n = data.bodies.len();
+    for mut f in lines {
+        let (column, keyword) = f.next("keyword")?;
+        match keyword {
+            "j2" => {
+                let i = f.index("body index", n)?;
+                let j2 = f.number("J2")?;
+                let radius = f.number("radius")?;
+                let at = f.pos;
+                let axis = if f.peek().is_some() { f.vector("axis")? } else { [0.0, 0.0, 1.0] };
+                let norm = f64::sqrt(axis[0] * axis[0] + axis[1] * axis[1] + axis[2] * axis[2]);
+                // no direction to normalize, only a given axis can get here
+                if !norm.is_finite() || norm == 0.0 {
+                    let tokens: Vec<&str> = f.tokens[at..at + 3].iter().map(|t| t.1).collect();
+                    return Err(f.error(f.tokens[at].0, LoadErrorKind::BadValue("axis", tokens.join(" "))));
+                }
+                data.forces.fields.push(Field::J2 { body : i, j2 : j2, radius : radius, axis : [axis[0] / norm, axis[1] / norm, axis[2] / norm] });
+            }
+            "uniform" => {
+                let g = f.vector("acceleration")?;
+                data.forces.fields.push(Field::Uniform { g : g });
+            }
+            "harmonic" => {
+                let center = f.vector("center")?;
+                let k = f.number("k")?;
+                data.forces.fields.push(Field::Harmonic { center : center, k : k });
+            }
+            "drag" => {
+                let i = f.index("body index", n)?;
+                let (column, law) = f.next("linear or quadratic")?;
+                if law != "linear" && law != "quadratic" {
+                    return Err(f.error(column, LoadErrorKind::BadValue("linear or quadratic", String::from(law))));
+                }
+                let k = f.number("k")?;
+                let mut medium = None;
+                if f.peek().is_some() {
+                    medium = Some(f.index("medium index", n)?);
+                }
+                data.forces.nongrav.push(NonGrav::Drag { body : i, k : k, quadratic : law == "quadratic", medium : medium });
+            }
+            "radiation" => {
+                let i = f.index("body index", n)?;
+                let beta = f.number("beta")?;
+                let at = f.pos;
+                let j = f.index("source index", n)?;
+                // the light would come from r = 0
+                if j == i {
+                    return Err(f.bad(at, "source index, not the body itself"));
+                }
+                data.forces.nongrav.push(NonGrav::Radiation { body : i, beta : beta, source : j });
+            }
+            "wall" => {
+                let i = f.index("body index", n)?;
+                let center = f.vector("center")?;
+                let min_radius = f.number("min_rad")?;
+                let max_radius = f.number("max_rad")?;
+                data.forces.constraints.push(Constraint::wall(i, center, min_radius, max_radius));
+            }
+            "spring" => {
+                let i = f.index("body index", n)?;
+                let center = f.vector("center")?;
+                let min_radius = f.number("min_rad")?;
+                let max_radius = f.number("max_rad")?;
+                let k = f.number("k")?;
+                data.forces.constraints.push(Constraint::spring(i, center, min_radius, max_radius, k));
+            }
+            "rattle" => {
+                let i = f.index("body index", n)?;
+                let center = f.vector("center")?;
+                let rad = f.number("rad")?;
+                data.forces.constraints.push(Constraint::rattle(i, center, rad));
+            }
+            "units" => {
+                let length = f.scale("unit of length", LENGTHS)?;
+                let mass = f.scale("unit of mass", MASSES)?;
+                data.units = Some(Units::with_g(length, mass, data.g));
+            }
+            _ => {
+                // body trailer
+                if keyword.parse::<usize>().is_err() {
+                    return Err(f.error(column, LoadErrorKind::UnknownLine(String::from(keyword))));
+                }
+                f.pos -= 1;
+                let i = f.index("body index", n)?;
+                let color = f.word("color")?;
+                let min_radius = f.number("min_rad")?;
+                let max_radius = f.number("max_rad")?;
+                let rad = f.number("rad")?;
+                let mut radius = 0.0;
+                if f.peek().is_some() {
+                    radius = f.number("radius")?;
+                }
+                data.bodies[i].color = String::from(color);
+                data.bodies[i].rad = rad;
+                data.particles.radius[i] = radius;
+                if min_radius > 0.0 || max_radius > 0.0 {
+                    data.forces.constraints.push(Constraint::wall(i, [0.0, 0.0, 0.0], min_radius, max_radius));
+                }
+            }
+        }
+        f.finish()?;
+    }
+    Ok(())
+}
+
+fn parse(text: &str) -> Result<System, LoadError> {
+    let mut data = System::new(0.001);
+    read(&mut data, "test.txt", text.as_bytes())?;
+    Ok(data)
+}
+
+// expects an error at line:column
+fn check(text: &str, line: usize, column: usize, code: &str) {
+    match parse(text) {
+        Ok(_) => {
+            println!("no error in {text:?}");
+            println!("{code}"); exit(1);
+        }
+        Err(e) => {
+            println!("{e}");
+            if e.line != line || e.column != column {
+                println!("{code}"); exit(1);
+            }
+        }
+    }
+}
+
+pub fn run_test() {
+    let good = "1 0.01\n2\n\nA 0 0 0 0 0 0 1 fixed\nB orbit A 1 0 0 0 0 90 0.001\n\
+                1 ff0000 0 0 3 0.1\nj2 0 0.01 0.1 0 1 0\ndrag 1 linear 0.1 0\nwall 1 0 0 0 0.5 2\nunits au 1e24\n";
+    let data = match parse(good) {
+        Ok(data) => data,
+        Err(e) => {
+            println!("{e}");
+            println!("Error1"); exit(1);
+        }
+    };
+    let p = &data.particles;
+    println!("loaded: {:?} {:?}", p.r(1), p.v(1));
+    if p.len() != 2 || !p.fixed[0] || f64::abs(p.y[1] - 1.0) > 1e-15 || f64::abs(p.vx[1] + f64::sqrt(1.001)) > 1e-15
+        || data.forces.softening != 0.01 || data.bodies[1].color != "ff0000" || p.radius[1] != 0.1
+        || data.forces.fields.len() != 1 || data.forces.nongrav.len() != 1 || data.forces.constraints.len() != 1
+        || data.units.map(|u| u.mass) != Some(1e24) {
+        println!("Error2"); exit(1);
+    }
+
+    check("1\n2\nA 0 0 0 0 0 0 1\nB 0 0 x 0 0 0 1\n", 4, 7, "Error3");
+    check("1\n3\nA 0 0 0 0 0 0 1\nB 0 1 0 0 0 0 1\n", 5, 1, "Error4");
+    check("1\n1\nA 0 0 0 0 0 0 1\n1 ff0000 0 0 3\n", 4, 1, "Error5");
+    check("1\n1\nA 0 0 0 0 0 0\n", 3, 14, "Error6");
+    check("1\n1\nA 0 0 0 0 0 0 1 fxed\n", 3, 17, "Error7");
+    check("1\n1\nA orbit C 1 0 0 0 0 0 1\n", 3, 9, "Error8");
+    check("1\n1\nA 0 0 0 0 0 0 1\ndrag 0 cubic 1\n", 4, 8, "Error9");
+    check("1\n1\nA 0 0 0 0 0 0 1\nunits au moon\n", 4, 10, "Error10");
+    let missing = load(&mut System::new(0.001), "no such file.txt");
+    if !matches!(missing, Err(LoadError { kind : LoadErrorKind::Io(_), .. })) {
+        println!("Error11"); exit(1);
+    }
+    // conics the a, e form cannot place
+    check("1\n2\nA 0 0 0 0 0 0 1\nB orbit A 1 1.5 0 0 0 0 0.001\n", 4, 11, "Error12");
+    check("1\n2\nA 0 0 0 0 0 0 1\nB orbit A -1 0.5 0 0 0 0 0.001\n", 4, 11, "Error13");
+    check("1\n2\nA 0 0 0 0 0 0 1\nB orbit A 1 1 0 0 0 0 0.001\n", 4, 13, "Error14");
+    check("1\n2\nA 0 0 0 0 0 0 1\nB orbit A 1 -0.1 0 0 0 0 0.001\n", 4, 13, "Error15");
+    check("1\n1\nA 0 0 0 0 0 0 1\nj2 0 0.01 0.1 0 0 0\n", 4, 15, "Error16");
+    check("1\n1\nA 0 0 0 0 0 0 1\nradiation 0 0.1 0\n", 4, 17, "Error17");
+    println!("Ok");
+}
@@ -8,6 +8,11 @@ use std::cell::RefCell;
 use std::ffi::OsStr;
 use gtk::gio;
 
+use solar_rs::output::{self, Format, Frame, Layout, FORMATS, format_by_name};
+use solar_rs::integrator::METHODS;
+use solar_rs::collision::{Event, MODES};
+use solar_rs::units::{Units, AU, DAY};
+
 pub struct SharedFromThisBase<T> {
     weak: RefCell<Weak<T>>,
 }
@@ -39,6 +44,8 @@ struct Body {
     name: String,
     r: [f64;3],
     v: [f64;3],
+    // a, e, i, node, peri, M, P around the primary, nan for the primary
+    elements: [f64;7],
     m: f64,
     // color
     cr: f64,
@@ -57,6 +64,7 @@ impl Body {
             name: String::new(),
             r: [0.0, 0.0, 0.0],
             v: [0.0, 0.0, 0.0],
+            elements: [f64::NAN; 7],
             m: 0.0,
             cr: 0.0,
             cg: 0.0,
@@ -66,6 +74,9 @@ impl Body {
     }
 }
 
+// buffer, bytes read and the error after a partial read
+type ReadAll = Result<(Vec<u8>, usize, Option<glib::Error>), (Vec<u8>, glib::Error)>;
+
 struct Preset {
     _name: String,
     input_file: String,
@@ -83,16 +94,33 @@ struct Context {
     base: SharedFromThisBase<RefCell<Context>>,
     bodies: Vec<Body>,
     active_body: i32,
+    // total energy of the last state and of the first one after the
+    // start or the last collision
+    energy: f64,
+    energy0: Option<f64>,
+    // time of the last state and the physical scale of the solver units
+    time: f64,
+    units: Option<Units>,
+    // the columns of a state, from the header
+    layout: Option<Layout>,
     //
     method: u32,
+    collisions: u32,
     dt: f64,
     input_file: String,
+    // the solver computes the diagnostics and elements only while they are shown
+    diagnostics: bool,
+    elements_shown: bool,
     //
     presets: Vec<Preset>,
     active_preset: u32,
     // controls
     r: Vec<glib::WeakRef<gtk::Label>>,
     v: Vec<glib::WeakRef<gtk::Label>>,
+    drift: glib::WeakRef<gtk::Label>,
+    time_label: glib::WeakRef<gtk::Label>,
+    status: glib::WeakRef<gtk::Label>,
+    elements: Vec<glib::WeakRef<gtk::Label>>,
     body_selector: glib::WeakRef<gtk::DropDown>,
     method_selector: glib::WeakRef<gtk::DropDown>,
     dt_selector: glib::WeakRef<gtk::SpinButton>,
@@ -103,6 +131,8 @@ struct Context {
     input: Option<gio::InputStream>,
     cancel_read: Option<gio::Cancellable>,
     line_input: Option<gio::DataInputStream>,
+    // binary frames or text lines from the solver
+    format: Format,
     header_processed: bool,
     suspend: bool,
     // timeout
@@ -118,10 +148,18 @@ impl Context {
             base: SharedFromThisBase::new(),
             bodies: Vec::new(),
             active_body: -1,
+            energy: 0.0,
+            energy0: None,
+            time: 0.0,
+            units: None,
+            layout: None,
             //
             method: 100,
+            collisions: 0,
             dt: 1e-3,
             input_file: String::from("2bodies.txt"),
+            diagnostics: true,
+            elements_shown: true,
             //
             presets: vec![
                 Preset{_name: String::from("2 Bodies"), input_file: String::from("2bodies.txt"), method: 1, dt: 0.00005},
@@ -132,6 +170,10 @@ impl Context {
             active_preset: 100,
             r: Vec::new(),
             v: Vec::new(),
+            drift: glib::WeakRef::new(),
+            time_label: glib::WeakRef::new(),
+            status: glib::WeakRef::new(),
+            elements: Vec::new(),
             body_selector: glib::WeakRef::new(),
             method_selector: glib::WeakRef::new(),
             dt_selector: glib::WeakRef::new(),
@@ -142,6 +184,7 @@ impl Context {
             input: None,
             cancel_read: None,
             line_input: None,
+            format: Format::Binary,
             header_processed: false,
             suspend: false,
             //
@@ -174,7 +217,14 @@ impl Context {
         self.header_processed = false;
         self.suspend = false;
         self.active_body = -1;
-        if self.method < 2 {
+        self.energy0 = None;
+        self.time = 0.0;
+        self.units = None;
+        self.layout = None;
+        if let Some(status) = self.status.upgrade() {
+            status.set_text("");
+        }
+        if (self.method as usize) < METHODS.len() {
             self.spawn();
             self.read_child();
         }
@@ -183,21 +233,29 @@ impl Context {
     fn spawn(&mut self) {
         let mut path = std::env::current_exe().unwrap();
         path.pop();
-        if self.method == 0 {
-            path.push("euler");
-        } else {
-            path.push("verlet");
-        }
+        path.push("nbody");
         let dt = self.dt.to_string();
-        let argv = [
+        let mut argv = vec![
             path.as_os_str(),
+            OsStr::new("--method"),
+            OsStr::new(METHODS[self.method as usize].0),
             OsStr::new("--input"),
             OsStr::new(&self.input_file),
             OsStr::new("--dt"),
             OsStr::new(&dt),
             OsStr::new("--T"),
-            OsStr::new("1e20")
+            OsStr::new("1e20"),
+            OsStr::new("--collisions"),
+            OsStr::new(MODES[self.collisions as usize].0),
+            OsStr::new("--format"),
+            OsStr::new(if self.format == Format::Binary { "binary" } else { "text" })
         ];
+        if self.diagnostics {
+            argv.push(OsStr::new("--diagnostics"));
+        }
+        if self.elements_shown {
+            argv.push(OsStr::new("--elements"));
+        }
         let subprocess = gio::Subprocess::newv(&argv, gio::SubprocessFlags::STDOUT_PIPE).expect("cannot start");
         let input = subprocess.stdout_pipe().unwrap();
         let line_input = gio::DataInputStream::new(&input);
@@ -207,38 +265,132 @@ impl Context {
         self.cancel_read.replace(gio::Cancellable::new());
     }
 
+    // stops the solver and says why, its own messages are on the terminal
+    fn fail(&mut self, message: &str) {
+        eprintln!("{message}");
+        if let Some(status) = self.status.upgrade() {
+            status.set_text(message);
+        }
+        self.stop();
+    }
+
+    // a short read is the end of the stream, a cancelled one comes from stop
+    fn read_all(&mut self, res: ReadAll) -> Option<Vec<u8>> {
+        match res {
+            Ok((bytes, n, None)) if n == bytes.len() => Some(bytes),
+            Err((_, e)) if e.matches(gio::IOErrorEnum::Cancelled) => None,
+            _ => {
+                self.fail("the solver stopped");
+                None
+            }
+        }
+    }
+
     fn read_child(&mut self) {
         let this = self.shared_from_this();
+        if self.format == Format::Binary {
+            // the fixed size frame header, then the payload
+            self.line_input.as_ref().unwrap().read_all_async(
+                vec![0u8; output::FRAME_HEADER],
+                0.into(),
+                self.cancel_read.as_ref(),
+                clone!(@strong this => move |x| { this.borrow_mut().on_frame_header(x); }) );
+            return;
+        }
         self.line_input.as_ref().unwrap().read_line_async(
             0.into(),
             self.cancel_read.as_ref(),
             clone!(@strong this => move |x| { this.borrow_mut().on_new_data(x); }) );
     }
 
+    fn on_frame_header(&mut self, res: ReadAll) {
+        let bytes = match self.read_all(res) {
+            Some(bytes) => bytes,
+            None => return
+        };
+        let (tag, len) = match output::parse_frame_header(&bytes) {
+            Some(header) => header,
+            None => return self.fail("bad frame from the solver")
+        };
+        let this = self.shared_from_this();
+        self.line_input.as_ref().unwrap().read_all_async(
+            vec![0u8; len],
+            0.into(),
+            self.cancel_read.as_ref(),
+            clone!(@strong this => move |x| { this.borrow_mut().on_frame(tag, x); }) );
+    }
+
+    fn on_frame(&mut self, tag: u8, res: ReadAll) {
+        let bytes = match self.read_all(res) {
+            Some(bytes) => bytes,
+            None => return
+        };
+        match output::parse_frame(tag, &bytes) {
+            Some(Frame::Header(text)) => {
+                for line in text.lines() {
+                    if !self.on_header_line(line) {
+                        return self.fail(&format!("bad header line from the solver: {line}"));
+                    }
+                }
+            }
+            Some(Frame::Event(event)) => {
+                if !self.apply_event(event) {
+                    return;
+                }
+            }
+            Some(Frame::State(values)) => self.on_state(&values),
+            None => return self.fail("bad frame from the solver")
+        }
+
+        if !self.suspend {
+            self.read_child();
+        }
+    }
+
     fn on_new_data(&mut self, res: Result<glib::collections::Slice<u8>, glib::Error>) {
-        if res.is_err() { return; }
-
-        let unwrapped = res.unwrap();
-        let mut parts = unwrapped.split(|x| *x == b' ' || *x == b'\n');
-        let first = parts.next().unwrap();
-        if first[0] == b't' {
-            // skip column names
-        } else if first[0] == b'#' {
+        let unwrapped = match res {
+            Ok(line) => line,
+            Err(e) if e.matches(gio::IOErrorEnum::Cancelled) => return,
+            Err(_) => return self.fail("the solver stopped")
+        };
+        // an empty line without a newline is the end of the stream
+        if unwrapped.is_empty() {
+            return self.fail("the solver stopped");
+        }
+        let line = match std::str::from_utf8(&unwrapped) {
+            Ok(line) => line,
+            Err(_) => return self.fail("bad line from the solver")
+        };
+        if let Some(event) = output::parse_event(line) {
+            if !self.apply_event(event) {
+                return;
+            }
+        } else if !self.on_header_line(line) {
+            let values: Vec<f64> = line.split_whitespace().map(|s| s.parse::<f64>().unwrap_or(f64::NAN)).collect();
+            self.on_state(&values);
+        }
+
+        if !self.suspend {
+            self.read_child();
+        }
+    }
+
+    // column names, units and body comments, false for anything else
+    fn on_header_line(&mut self, line: &str) -> bool {
+        if let Some(layout) = output::parse_columns(line) {
+            self.layout = Some(layout);
+        } else if let Some(units) = output::parse_units(line) {
+            self.units = Some(units);
+        } else if let Some((desc, m)) = output::parse_comment(line) {
             let mut body = Body::new();
             // header
-            let name = std::str::from_utf8(parts.next().unwrap()).unwrap();
-            let m = std::str::from_utf8(parts.next().unwrap()).unwrap().parse::<f64>().unwrap();
-            let color = match parts.next() {
-                Some(s) => i64::from_str_radix(std::str::from_utf8(s).unwrap(), 16).unwrap(),
-                None => 0
-            };
+            let name = desc.name.as_str();
+            // gray when the file has no valid color
+            let color = i64::from_str_radix(&desc.color, 16).unwrap_or(0x808080);
             let b = (((color >> 0) & 0xff) as f64) / 256.0;
             let g = (((color >> 8) & 0xff) as f64) / 256.0;
             let r = (((color >> 16) & 0xff) as f64) / 256.0;
-            let rad = match parts.next() {
-                Some(s) => std::str::from_utf8(s).unwrap().parse::<f64>().unwrap(),
-                None => 1.0
-            };
+            let rad = desc.rad;
             println!("{} {} r{} g{} b{} {}", name, m, r, g, b, rad);
             body.name = String::from(name);
             body.m = m;
@@ -247,7 +399,15 @@ impl Context {
             body.cr = r;
             body.rad = rad;
             self.bodies.push(body);
-        } else if !self.header_processed {
+        } else {
+            return false;
+        }
+        true
+    }
+
+    // the values of one state, time first
+    fn on_state(&mut self, values: &[f64]) {
+        if !self.header_processed {
             self.header_processed = true;
             let model: gtk::StringList = self.body_selector.upgrade().unwrap().model().unwrap().downcast().unwrap();
             for i in 0..self.bodies.len() {
@@ -257,33 +417,76 @@ impl Context {
             self.active_body = 0;
         }
 
-        if self.header_processed {
-            // time in first
-            for i in 0..self.bodies.len() {
-                for j in 0..3 {
-                    match parts.next() {
-                        Some(s) => {
-                            self.bodies[i].r[j] = std::str::from_utf8(s).unwrap().parse::<f64>().unwrap()
-                        },
-                        _ => break
-                    }
+        // r and v first, without a header
+        let layout = self.layout.unwrap_or(Layout { per_body : 6, diagnostics : 0, elements : 0 });
+        let mut parts = values.iter().copied();
+        self.time = parts.next().unwrap_or(0.0);
+        for i in 0..self.bodies.len() {
+            let mut body = parts.by_ref().take(layout.per_body);
+            for j in 0..3 {
+                match body.next() {
+                    Some(x) => self.bodies[i].r[j] = x,
+                    _ => break
                 }
-                for j in 0..3 {
-                    match parts.next() {
-                        Some(s) => {
-                            self.bodies[i].v[j] = std::str::from_utf8(s).unwrap().parse::<f64>().unwrap()
-                        },
-                        _ => break
-                    }
+            }
+            for j in 0..3 {
+                match body.next() {
+                    Some(x) => self.bodies[i].v[j] = x,
+                    _ => break
                 }
             }
-            self.update_all();
-            self.suspend = true;
+            body.for_each(drop);
+        }
+        // diagnostics follow the bodies, kinetic and potential energy first,
+        // then momentum, angular momentum and center of mass
+        let diagnostics: Vec<f64> = parts.by_ref().take(layout.diagnostics).collect();
+        if diagnostics.len() >= 2 {
+            let energy = diagnostics[0] + diagnostics[1];
+            self.energy = energy;
+            if self.energy0.is_none() {
+                self.energy0 = Some(energy);
+            }
+        }
+        // the elements of every body come last
+        for i in 0..self.bodies.len() {
+            let mut el = parts.by_ref().take(layout.elements);
+            for j in 0..7 {
+                self.bodies[i].elements[j] = el.next().unwrap_or(f64::NAN);
+            }
+            el.for_each(drop);
         }
+        self.update_all();
+        self.suspend = true;
+    }
 
-        if !self.suspend {
-            self.read_child();
+    // j was merged into i: drop it from the list and the dropdown,
+    // the selection follows the surviving body; false after a bad event
+    fn apply_event(&mut self, event: Event) -> bool {
+        let (Event::Merge(i, j) | Event::Bounce(i, j)) = event;
+        if i >= self.bodies.len() || j >= self.bodies.len() {
+            self.fail("bad event from the solver");
+            return false;
+        }
+        // collisions need not keep the energy, the drift starts over
+        self.energy0 = None;
+        if let Event::Merge(i, j) = event {
+            println!("Merge {} {}", self.bodies[i].name, self.bodies[j].name);
+            self.bodies[i].m += self.bodies[j].m;
+            self.bodies.remove(j);
+            let active = self.active_body;
+            if active == j as i32 {
+                self.active_body = i as i32;
+            } else if active > j as i32 {
+                self.active_body = active - 1;
+            }
+            let selector = self.body_selector.upgrade().unwrap();
+            let model: gtk::StringList = selector.model().unwrap().downcast().unwrap();
+            model.remove(j as u32);
+            if self.active_body >= 0 {
+                selector.set_selected(self.active_body as u32);
+            }
         }
+        true
     }
 
     fn method_changed(&mut self, selector: &gtk::DropDown) {
@@ -294,6 +497,40 @@ impl Context {
         }
     }
 
+    fn collisions_changed(&mut self, selector: &gtk::DropDown) {
+        let active = selector.selected();
+        if self.collisions != active {
+            self.collisions = active;
+            self.start();
+        }
+    }
+
+    fn diagnostics_toggled(&mut self, button: &gtk::CheckButton) {
+        if self.diagnostics != button.is_active() {
+            self.diagnostics = button.is_active();
+            self.drift.upgrade().unwrap().set_visible(self.diagnostics);
+            self.start();
+        }
+    }
+
+    fn elements_toggled(&mut self, button: &gtk::CheckButton) {
+        if self.elements_shown != button.is_active() {
+            self.elements_shown = button.is_active();
+            for el in self.elements.iter() {
+                el.upgrade().unwrap().set_visible(self.elements_shown);
+            }
+            self.start();
+        }
+    }
+
+    fn format_changed(&mut self, selector: &gtk::DropDown) {
+        let format = format_by_name(FORMATS[selector.selected() as usize].0).unwrap();
+        if self.format != format {
+            self.format = format;
+            self.start();
+        }
+    }
+
     fn preset_changed(&mut self, selector: &gtk::DropDown) {
         let active = selector.selected();
         if active != self.active_preset {
@@ -340,18 +577,53 @@ impl Context {
         }
     }
 
+    // factors and names of the shown length, velocity and time units,
+    // au, km/s and days when the solver gives its units
+    fn shown_units(&self) -> [(f64, &'static str); 3] {
+        match &self.units {
+            Some(u) => [(u.length / AU, " au"), (u.length / u.time / 1e3, " km/s"), (u.time / DAY, " d")],
+            None => [(1.0, ""); 3]
+        }
+    }
+
     fn update_all(&mut self) {
+        let [length, velocity, time] = self.shown_units();
         let i = self.active_body;
         if i >= 0 && i < (self.bodies.len() as i32) {
             let body = &self.bodies[i as usize];
             let cx = ['x', 'y', 'z'];
             for j in 0..3 {
-                let fmt = format!("<tt>r<sub>{}</sub> = {:+.8e}</tt>", cx[j], body.r[j]);
+                let fmt = format!("<tt>r<sub>{}</sub> = {:+.8e}{}</tt>", cx[j], body.r[j] * length.0, length.1);
                 self.r[j].upgrade().unwrap().set_label(&fmt);
-                let fmt = format!("<tt>v<sub>{}</sub> = {:+.8e}</tt>", cx[j], body.v[j]);
+                let fmt = format!("<tt>v<sub>{}</sub> = {:+.8e}{}</tt>", cx[j], body.v[j] * velocity.0, velocity.1);
                 self.v[j].upgrade().unwrap().set_label(&fmt);
             }
         }
+        let fmt = format!("<tt>t = {:+.8e}{}</tt>", self.time * time.0, time.1);
+        self.time_label.upgrade().unwrap().set_label(&fmt);
+        if let (true, Some(e0)) = (self.diagnostics, self.energy0) {
+            let fmt = format!("<tt>ΔE/E = {:+.8e}</tt>", (self.energy - e0) / e0.abs());
+            self.drift.upgrade().unwrap().set_label(&fmt);
+        }
+        if self.elements_shown && i >= 0 && i < (self.bodies.len() as i32) {
+            let el = &self.bodies[i as usize].elements;
+            let names = ["a", "e", "i", "Ω", "ω", "M", "P"];
+            for j in 0..7 {
+                // angles in degrees
+                let (x, unit) = match j {
+                    0 => (el[j] * length.0, length.1),
+                    2..=5 => (el[j].to_degrees(), ""),
+                    6 => (el[j] * time.0, time.1),
+                    _ => (el[j], "")
+                };
+                let fmt = if x.is_nan() {
+                    format!("<tt>{} = -</tt>", names[j])
+                } else {
+                    format!("<tt>{} = {:+.8e}{}</tt>", names[j], x, unit)
+                };
+                self.elements[j].upgrade().unwrap().set_label(&fmt);
+            }
+        }
         gtk::Widget::queue_draw(&self.drawing_area.upgrade().unwrap().upcast());
     }
 
@@ -460,11 +732,36 @@ fn control_widget(ctx: &Rc<RefCell<Context>>) -> gtk::Widget {
     preset_selector.connect_state_flags_changed(clone!(@strong ctx => move |a, _| ctx.borrow_mut().preset_changed(a) ));
     bx.append(&preset_selector);
 
-    let methods = ["Euler", "Verlet"];
+    let methods: Vec<&str> = METHODS.iter().map(|m| m.1).collect();
     bx.append(&gtk::Label::new(Some("Method:")));
     let method_selector = gtk::DropDown::from_strings(&methods);
     method_selector.connect_state_flags_changed(clone!(@strong ctx => move |a, _| ctx.borrow_mut().method_changed(a) ));
     bx.append(&method_selector);
+
+    let modes: Vec<&str> = MODES.iter().map(|m| m.1).collect();
+    bx.append(&gtk::Label::new(Some("Collisions:")));
+    let collisions_selector = gtk::DropDown::from_strings(&modes);
+    collisions_selector.connect_state_flags_changed(clone!(@strong ctx => move |a, _| ctx.borrow_mut().collisions_changed(a) ));
+    bx.append(&collisions_selector);
+
+    let formats: Vec<&str> = FORMATS.iter().map(|f| f.1).collect();
+    bx.append(&gtk::Label::new(Some("Output:")));
+    let format_selector = gtk::DropDown::from_strings(&formats);
+    let current = FORMATS.iter().position(|f| format_by_name(f.0) == Some(ctx.borrow().format)).unwrap();
+    format_selector.set_selected(current as u32);
+    format_selector.connect_state_flags_changed(clone!(@strong ctx => move |a, _| ctx.borrow_mut().format_changed(a) ));
+    bx.append(&format_selector);
+
+    let diagnostics = gtk::CheckButton::with_label("Energy drift");
+    diagnostics.set_active(ctx.borrow().diagnostics);
+    diagnostics.connect_toggled(clone!(@strong ctx => move |x| ctx.borrow_mut().diagnostics_toggled(x)));
+    bx.append(&diagnostics);
+
+    let elements = gtk::CheckButton::with_label("Orbital elements");
+    elements.set_active(ctx.borrow().elements_shown);
+    elements.connect_toggled(clone!(@strong ctx => move |x| ctx.borrow_mut().elements_toggled(x)));
+    bx.append(&elements);
+
     bx.append(&gtk::Label::new(Some("Input:")));
     let entry = gtk::Entry::new();
     let buffer = entry.buffer();
@@ -483,7 +780,13 @@ fn control_widget(ctx: &Rc<RefCell<Context>>) -> gtk::Widget {
     }));
     bx.append(&dt);
 
+    let status = gtk::Label::new(None);
+    status.set_wrap(true);
+    status.set_width_chars(30);
+    bx.append(&status);
+
     ctx.borrow_mut().method_selector.set(Some(&method_selector.into()));
+    ctx.borrow_mut().status.set(Some(&status));
     ctx.borrow_mut().dt_selector.set(Some(&dt));
     ctx.borrow_mut().entry_buffer.set(Some(&buffer));
 
@@ -515,6 +818,23 @@ fn info_widget(ctx: &Rc<RefCell<Context>>) -> gtk::Widget {
         vx.set_use_markup(true);
         ctx.borrow_mut().v.push(gtk::prelude::ObjectExt::downgrade(&vx));
     }
+    let time = gtk::Label::new(Some("-"));
+    bx.append(&time);
+    time.set_width_chars(30);
+    time.set_use_markup(true);
+    ctx.borrow_mut().time_label.set(Some(&time));
+    let drift = gtk::Label::new(Some("-"));
+    bx.append(&drift);
+    drift.set_width_chars(30);
+    drift.set_use_markup(true);
+    ctx.borrow_mut().drift.set(Some(&drift));
+    for _i in 0..7 {
+        let el = gtk::Label::new(Some("-"));
+        bx.append(&el);
+        el.set_width_chars(30);
+        el.set_use_markup(true);
+        ctx.borrow_mut().elements.push(gtk::prelude::ObjectExt::downgrade(&el));
+    }
 
     ctx.borrow_mut().body_selector.set(Some(&body_selector.into()));
 
@@ -0,0 +1,141 @@
+use std::process::exit;
+
+use crate::body::{Body, Particles, System};
+use crate::integrator::Integrator;
+use crate::dopri5::Dopri5;
+
+// per body forces that are not gravity
+#[derive(Clone, Debug)]
+pub enum NonGrav {
+    // a = -k u (linear) or a = -k |u| u (quadratic), u is the velocity
+    // relative to the medium body or to the frame when there is none
+    Drag { body: usize, k: f64, quadratic: bool, medium: Option<usize> },
+    // radiation pressure of the luminous body source, beta times its
+    // gravity pointing away from it
+    Radiation { body: usize, beta: f64, source: usize }
+}
+
+impl NonGrav {
+    pub fn velocity_dependent(&self) -> bool {
+        matches!(self, NonGrav::Drag { .. })
+    }
+
+    // index remapping after body i is removed, None when the force goes away
+    pub fn without(&self, i: usize) -> Option<NonGrav> {
+        let shift = |j: usize| if j > i { j - 1 } else { j };
+        match *self {
+            NonGrav::Drag { body, k, quadratic, medium } => {
+                if body == i || medium == Some(i) {
+                    return None;
+                }
+                Some(NonGrav::Drag { body : shift(body), k : k, quadratic : quadratic, medium : medium.map(shift) })
+            }
+            NonGrav::Radiation { body, beta, source } => {
+                if body == i || source == i {
+                    return None;
+                }
+                Some(NonGrav::Radiation { body : shift(body), beta : beta, source : shift(source) })
+            }
+        }
+    }
+}
+
+// drag accelerations on top of p.ax, p.ay, p.az
+pub fn add_drag(p: &mut Particles, forces: &[NonGrav]) {
+    for f in forces {
+        if let NonGrav::Drag { body, k, quadratic, medium } = *f {
+            if p.fixed[body] { continue; }
+            let mut u = p.v(body);
+            if let Some(j) = medium {
+                let vm = p.v(j);
+                for l in 0..3 {
+                    u[l] -= vm[l];
+                }
+            }
+            let mut c = k;
+            if quadratic {
+                c *= f64::sqrt(u[0] * u[0] + u[1] * u[1] + u[2] * u[2]);
+            }
+            p.ax[body] -= c * u[0];
+            p.ay[body] -= c * u[1];
+            p.az[body] -= c * u[2];
+        }
+    }
+}
+
+// radiation pressure accelerations on top of p.ax, p.ay, p.az
+pub fn add_radiation(p: &mut Particles, g: f64, forces: &[NonGrav]) {
+    for f in forces {
+        if let NonGrav::Radiation { body, beta, source } = *f {
+            if p.fixed[body] { continue; }
+            let (rb, rs) = (p.r(body), p.r(source));
+            let d = [rb[0] - rs[0], rb[1] - rs[1], rb[2] - rs[2]];
+            let r2 = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+            let f = beta * g * p.m[source] / (r2 * r2.sqrt());
+            p.ax[body] += f * d[0];
+            p.ay[body] += f * d[1];
+            p.az[body] += f * d[2];
+        }
+    }
+}
+
+// dust grain around a star, slowed by linear drag and pushed by the light
+// of the star, and a second grain with quadratic drag against the first
+fn dust(dt: f64) -> System {
+    let mut data = System::new(dt);
+    data.add(Body::new("star"), [0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 1.0);
+    data.add(Body::new("d1"), [1.0, 0.0, 0.0], [0.0, 0.9, 0.1], 1e-6);
+    data.add(Body::new("d2"), [0.0, 1.5, 0.0], [-0.8, 0.0, 0.0], 1e-6);
+    let f = &mut data.forces.nongrav;
+    f.push(NonGrav::Drag { body : 1, k : 0.2, quadratic : false, medium : None });
+    f.push(NonGrav::Radiation { body : 1, beta : 0.3, source : 0 });
+    f.push(NonGrav::Drag { body : 2, k : 0.3, quadratic : true, medium : Some(1) });
+    data
+}
+
+fn error(dt: f64, method: &mut dyn Integrator, reference: &System, max_time: f64) -> f64 {
+    let mut data = dust(dt);
+    let steps = (max_time / dt).round() as usize;
+    method.init(&mut data);
+    for _ in 0..steps {
+        method.step(&mut data);
+    }
+    let mut max_err: f64 = 0.0;
+    for i in 0..data.particles.len() {
+        let (a, b) = (data.particles.r(i), reference.particles.r(i));
+        for k in 0..3 {
+            max_err = max_err.max(f64::abs(a[k] - b[k]));
+        }
+    }
+    max_err
+}
+
+// the velocity dependent drag must not cost the method its order
+pub fn run_test(method: &mut dyn Integrator) {
+    let max_time = 2.0;
+    let mut reference = dust(max_time);
+    let mut dopri = Dopri5::new(1e-13, 1e-15);
+    dopri.init(&mut reference);
+    dopri.step(&mut reference);
+
+    if method.adaptive() {
+        let err = error(0.01, method, &reference, max_time);
+        println!("drag: {err}");
+        if err > 1e-7 {
+            println!("Error1"); exit(1);
+        }
+        println!("Ok");
+        return;
+    }
+
+    let order = method.order() as f64;
+    let dt = if method.order() > 4 { 0.1 } else { 0.005 };
+    let err1 = error(dt, method, &reference, max_time);
+    let err2 = error(dt / 2.0, method, &reference, max_time);
+    let p = f64::log2(err1 / err2);
+    println!("drag: {err1} {err2} order {p}");
+    if p < order - 0.5 {
+        println!("Error2"); exit(1);
+    }
+    println!("Ok");
+}
@@ -0,0 +1,378 @@
+use std::io::{self, Write};
+use std::process::exit;
+
+use crate::body::{Body, System};
+use crate::collision::Event;
+use crate::diagnostics::measure;
+use crate::elements::{heaviest, relative};
+use crate::units::Units;
+
+// text lines, or binary frames: a tag byte, the length of the payload as
+// a little endian u32 and the payload. The header frame holds the text
+// header, events are text lines too, and a state frame is the values of
+// a text line as little endian f64
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub enum Format {
+    #[default]
+    Text,
+    Binary
+}
+
+pub const FORMATS: &[(&str, &str)] = &[
+    ("text", "Text"),
+    ("binary", "Binary"),
+];
+
+pub fn format_by_name(name: &str) -> Option<Format> {
+    match name {
+        "text" => Some(Format::Text),
+        "binary" => Some(Format::Binary),
+        _ => None
+    }
+}
+
+pub const FRAME_HEADER: usize = 5;
+// longer payloads are taken for corrupted data
+pub const MAX_FRAME: usize = 1 << 28;
+pub const HEADER: u8 = b'H';
+pub const STATE: u8 = b'S';
+pub const EVENT: u8 = b'E';
+
+#[derive(Debug, PartialEq)]
+pub enum Frame {
+    Header(String),
+    State(Vec<f64>),
+    Event(Event)
+}
+
+// optional columns after the bodies and the format of the output
+#[derive(Clone, Default)]
+pub struct Columns {
+    pub format: Format,
+    pub diagnostics: bool,
+    // osculating elements of every body around the primary
+    pub elements: bool,
+    // name of the primary, the most massive body when None
+    pub primary: Option<String>
+}
+
+impl Columns {
+    // the primary can change when bodies merge, so it is looked up every time
+    fn primary(&self, data: &System) -> usize {
+        match &self.primary {
+            Some(name) => data.bodies.iter().position(|b| &b.name == name)
+                .unwrap_or_else(|| heaviest(&data.particles)),
+            None => heaviest(&data.particles)
+        }
+    }
+}
+
+// names of the diagnostics columns that follow the bodies
+const DIAGNOSTICS: &str = "K U p,0 p,1 p,2 L,0 L,1 L,2 c,0 c,1 c,2 ";
+
+// column names, the units and one comment line per body
+pub fn header(data: &System, columns: &Columns) -> String {
+    let mut s = String::from("t ");
+    for i in 0..data.bodies.len() {
+        for j in 0..3 {
+            s += &format!("r{i},{j} ");
+        }
+        for j in 0..3 {
+            s += &format!("v{i},{j} ");
+        }
+    }
+    if columns.diagnostics {
+        s += DIAGNOSTICS;
+    }
+    if columns.elements {
+        for i in 0..data.bodies.len() {
+            s += &format!("a{i} e{i} i{i} node{i} peri{i} M{i} P{i} ");
+        }
+    }
+    s += "\n";
+    // the internal units in m, kg and s when they are physical
+    if let Some(u) = &data.units {
+        s += &format!("units {} {} {}\n", u.length, u.mass, u.time);
+    }
+    // comment
+    for (b, m) in data.bodies.iter().zip(&data.particles.m) {
+        s += &format!("# {} {} {} {}\n", b.name, m, b.color, b.rad);
+    }
+    s
+}
+
+// t and the bodies; with diagnostics the values go on with kinetic and
+// potential energy, momentum, angular momentum and center of mass; the
+// elements follow, angles in radians and nan for the primary
+pub fn values(data: &System, t: f64, columns: &Columns) -> Vec<f64> {
+    let p = &data.particles;
+    let mut x = Vec::with_capacity(1 + 6 * p.len() + 11 + 7 * p.len());
+    x.push(t);
+    for i in 0..p.len() {
+        x.extend_from_slice(&[p.x[i], p.y[i], p.z[i], p.vx[i], p.vy[i], p.vz[i]]);
+    }
+    if columns.diagnostics {
+        let d = measure(data);
+        x.extend_from_slice(&[d.kinetic, d.potential]);
+        for q in [d.momentum, d.angular_momentum, d.center_of_mass] {
+            x.extend_from_slice(&q);
+        }
+    }
+    if columns.elements {
+        let primary = columns.primary(data);
+        for i in 0..p.len() {
+            match relative(p, data.g, primary, i) {
+                Some(el) => x.extend_from_slice(&[el.a, el.e, el.i, el.node, el.peri, el.mean_anomaly, el.period]),
+                None => x.extend_from_slice(&[f64::NAN; 7])
+            }
+        }
+    }
+    x
+}
+
+fn write_frame(out: &mut impl Write, tag: u8, payload: &[u8]) -> io::Result<()> {
+    out.write_all(&[tag])?;
+    out.write_all(&(payload.len() as u32).to_le_bytes())?;
+    out.write_all(payload)?;
+    out.flush()
+}
+
+pub fn write_header(out: &mut impl Write, data: &System, columns: &Columns) -> io::Result<()> {
+    let s = header(data, columns);
+    match columns.format {
+        Format::Text => out.write_all(s.as_bytes()),
+        Format::Binary => write_frame(out, HEADER, s.as_bytes())
+    }
+}
+
+pub fn write_state(out: &mut impl Write, data: &System, t: f64, columns: &Columns) -> io::Result<()> {
+    let x = values(data, t, columns);
+    match columns.format {
+        Format::Text => {
+            let mut s = String::new();
+            for v in x {
+                if v.is_nan() {
+                    s += "nan ";
+                } else {
+                    s += &format!("{v} ");
+                }
+            }
+            s += "\n";
+            out.write_all(s.as_bytes())
+        }
+        Format::Binary => {
+            let mut payload = Vec::with_capacity(8 * x.len());
+            for v in x {
+                payload.extend_from_slice(&v.to_le_bytes());
+            }
+            write_frame(out, STATE, &payload)
+        }
+    }
+}
+
+pub fn write_event(out: &mut impl Write, event: &Event, t: f64, columns: &Columns) -> io::Result<()> {
+    let s = match event {
+        Event::Merge(i, j) => format!("merge {t} {i} {j}\n"),
+        Event::Bounce(i, j) => format!("bounce {t} {i} {j}\n")
+    };
+    match columns.format {
+        Format::Text => out.write_all(s.as_bytes()),
+        Format::Binary => write_frame(out, EVENT, s.as_bytes())
+    }
+}
+
+// the reader went away, like the GUI stopping the solver
+fn check(result: io::Result<()>) {
+    if result.is_err() {
+        exit(0);
+    }
+}
+
+pub fn print_header(data: &System, columns: &Columns) {
+    check(write_header(&mut io::stdout().lock(), data, columns));
+}
+
+pub fn print(data: &System, t: f64, columns: &Columns) {
+    check(write_state(&mut io::stdout().lock(), data, t, columns));
+}
+
+// numbers of columns after t: per body, of the diagnostics and of the
+// elements per body
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Layout {
+    pub per_body: usize,
+    pub diagnostics: usize,
+    pub elements: usize
+}
+
+// parses the column names line written by print_header: r0,0 and v0,0
+// belong to body 0, a0 to its elements, the names without a body index
+// to the diagnostics
+pub fn parse_columns(line: &str) -> Option<Layout> {
+    let mut parts = line.split_whitespace();
+    if parts.next() != Some("t") {
+        return None;
+    }
+    let (mut state, mut diagnostics, mut elements, mut bodies) = (0, 0, 0, 0);
+    for name in parts {
+        let digits = name.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+        let index = digits.split(',').next().unwrap();
+        match index.parse::<usize>() {
+            Ok(i) => {
+                bodies = bodies.max(i + 1);
+                if digits.contains(',') { state += 1; } else { elements += 1; }
+            }
+            Err(_) => diagnostics += 1
+        }
+    }
+    if bodies == 0 {
+        return Some(Layout { per_body : 0, diagnostics : diagnostics, elements : 0 });
+    }
+    Some(Layout { per_body : state / bodies, diagnostics : diagnostics, elements : elements / bodies })
+}
+
+// parses a "# name m color rad" comment line written by print_header,
+// returns the body and its mass
+pub fn parse_comment(line: &str) -> Option<(Body, f64)> {
+    let mut parts = line.split_whitespace();
+    if parts.next() != Some("#") {
+        return None;
+    }
+    let name = parts.next()?;
+    let m = parts.next()?.parse::<f64>().ok()?;
+    let mut body = Body::new(name);
+    if let Some(color) = parts.next() {
+        body.color = String::from(color);
+    }
+    if let Some(rad) = parts.next() {
+        body.rad = rad.parse::<f64>().ok()?;
+    }
+    Some((body, m))
+}
+
+// parses the "units length mass time" line written by print_header
+pub fn parse_units(line: &str) -> Option<Units> {
+    let mut parts = line.split_whitespace();
+    if parts.next() != Some("units") {
+        return None;
+    }
+    let mut next = || parts.next()?.parse::<f64>().ok();
+    Some(Units { length : next()?, mass : next()?, time : next()? })
+}
+
+// "merge t i j" or "bounce t i j", printed before the state at time t
+pub fn print_event(event: &Event, t: f64, columns: &Columns) {
+    check(write_event(&mut io::stdout().lock(), event, t, columns));
+}
+
+pub fn parse_event(line: &str) -> Option<Event> {
+    let mut parts = line.split_whitespace();
+    let kind = parts.next()?;
+    parts.next()?.parse::<f64>().ok()?;
+    let i = parts.next()?.parse::<usize>().ok()?;
+    let j = parts.next()?.parse::<usize>().ok()?;
+    match kind {
+        "merge" => Some(Event::Merge(i, j)),
+        "bounce" => Some(Event::Bounce(i, j)),
+        _ => None
+    }
+}
+
+// tag and payload length of a binary frame, None for an unknown tag or
+// a length over MAX_FRAME
+pub fn parse_frame_header(bytes: &[u8]) -> Option<(u8, usize)> {
+    let b = bytes.get(..FRAME_HEADER)?;
+    let len = u32::from_le_bytes([b[1], b[2], b[3], b[4]]) as usize;
+    if ![HEADER, STATE, EVENT].contains(&b[0]) || len > MAX_FRAME {
+        return None;
+    }
+    Some((b[0], len))
+}
+
+pub fn parse_frame(tag: u8, payload: &[u8]) -> Option<Frame> {
+    match tag {
+        HEADER => Some(Frame::Header(String::from(std::str::from_utf8(payload).ok()?))),
+        STATE => {
+            if !payload.len().is_multiple_of(8) {
+                return None;
+            }
+            Some(Frame::State(payload.chunks_exact(8)
+                .map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+                .collect()))
+        }
+        EVENT => Some(Frame::Event(parse_event(std::str::from_utf8(payload).ok()?)?)),
+        _ => None
+    }
+}
+
+// the frames of a whole binary stream
+fn read_frames(mut bytes: &[u8]) -> Option<Vec<Frame>> {
+    let mut frames = Vec::new();
+    while !bytes.is_empty() {
+        let (tag, len) = parse_frame_header(bytes)?;
+        let payload = bytes.get(FRAME_HEADER..FRAME_HEADER + len)?;
+        frames.push(parse_frame(tag, payload)?);
+        bytes = &bytes[FRAME_HEADER + len..];
+    }
+    Some(frames)
+}
+
+pub fn run_test() {
+    let mut data = System::new(0.001);
+    data.add(Body::new("a"), [0.0, 0.0, 0.0], [0.0, -0.001, 0.0], 1.0);
+    data.add(Body::new("b"), [1.0, 0.0, 0.0], [0.0, 1.0 / 3.0, 0.0], 1e-3);
+    let text = Columns { diagnostics : true, elements : true, ..Columns::default() };
+    let binary = Columns { format : Format::Binary, ..text.clone() };
+    let event = Event::Merge(0, 1);
+
+    let mut t = Vec::new();
+    let mut b = Vec::new();
+    for (out, columns) in [(&mut t, &text), (&mut b, &binary)] {
+        write_header(out, &data, columns).unwrap();
+        write_event(out, &event, 0.5, columns).unwrap();
+        write_state(out, &data, 0.5, columns).unwrap();
+    }
+    let t = String::from_utf8(t).unwrap();
+    let frames = read_frames(&b);
+    println!("{} text bytes, {} binary bytes", t.len(), b.len());
+    let lines: Vec<&str> = t.lines().collect();
+    let line_count = lines.len();
+    // header lines, the event and the state
+    let frames = match frames {
+        Some(frames) if frames.len() == 3 && line_count == 5 => frames,
+        _ => {
+            println!("Error1"); exit(1);
+        }
+    };
+
+    // the same header and event, the state to the last bit
+    let parsed: Vec<f64> = lines[4].split_whitespace().map(|s| s.parse::<f64>().unwrap()).collect();
+    let same = |a: &[f64], b: &[f64]| a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.to_bits() == y.to_bits() || x.is_nan() && y.is_nan());
+    if frames[0] != Frame::Header(lines[..3].iter().map(|l| format!("{l}\n")).collect())
+        || frames[1] != Frame::Event(event) {
+        println!("{:?}", frames);
+        println!("Error2"); exit(1);
+    }
+    match &frames[2] {
+        Frame::State(x) if same(x, &parsed) && x.len() == 1 + 12 + 11 + 14 => {}
+        _ => {
+            println!("Error3"); exit(1);
+        }
+    }
+
+    // the column counts come from the names
+    let layout = parse_columns(lines[0]);
+    let bare = parse_columns(header(&data, &Columns::default()).lines().next().unwrap());
+    println!("{:?} {:?}", layout, bare);
+    if layout != Some(Layout { per_body : 6, diagnostics : 11, elements : 7 })
+        || bare != Some(Layout { per_body : 6, diagnostics : 0, elements : 0 }) || parse_columns(lines[1]).is_some() {
+        println!("Error4"); exit(1);
+    }
+
+    // a cut stream, an unknown tag and a huge length are errors
+    if read_frames(&b[..b.len() - 1]).is_some() || parse_frame(b'X', &[]).is_some()
+        || parse_frame_header(&[b'X', 0, 0, 0, 0]).is_some() || parse_frame_header(&[STATE, 255, 255, 255, 255]).is_some() {
+        println!("Error5"); exit(1);
+    }
+    println!("Ok");
+}
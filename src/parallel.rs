@@ -0,0 +1,139 @@
+use std::process::exit;
+
+use rayon::prelude::*;
+
+use crate::body::Particles;
+use crate::gravity::direct;
+
+// sizes the global rayon pool, call once before the first force evaluation
+pub fn set_threads(threads: usize) {
+    let _ = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global();
+}
+
+// rows [lo, hi) of the upper triangle with about the same number of pairs each
+fn chunks(n: usize, count: usize) -> Vec<(usize, usize)> {
+    let total = n * n.saturating_sub(1) / 2;
+    let per_chunk = total / count.max(1) + 1;
+    let mut out = Vec::new();
+    let mut lo = 0;
+    let mut pairs = 0;
+    for i in 0..n {
+        pairs += n - 1 - i;
+        if pairs >= per_chunk || i + 1 == n {
+            out.push((lo, i + 1));
+            lo = i + 1;
+            pairs = 0;
+        }
+    }
+    out
+}
+
+// pairwise sum over i < j using a_j -= m_i / m_j * a_i, rows are split
+// into one chunk per thread with the same number of pairs and every chunk
+// accumulates into its own buffer, so there are no more buffers than threads.
+// The buffers are added in chunk order, so for a given number of threads the
+// result is reproducible; it differs from gravity::direct only by summation
+// order, within about 1e-13 of the rms acceleration.
+pub fn direct_symmetric(p: &Particles, g: f64, eps2: f64) -> Vec<[f64; 3]> {
+    let n = p.len();
+    let parts = chunks(n, rayon::current_num_threads());
+
+    let partial: Vec<Vec<[f64; 3]>> = parts.par_iter().map(|&(lo, hi)| {
+        let mut acc = vec![[0.0, 0.0, 0.0]; n];
+        for i in lo..hi {
+            let (xi, yi, zi, mi) = (p.x[i], p.y[i], p.z[i], p.m[i]);
+            let mut a = [0.0, 0.0, 0.0];
+            for (j, aj) in acc.iter_mut().enumerate().skip(i + 1) {
+                let d = [p.x[j] - xi, p.y[j] - yi, p.z[j] - zi];
+                let r2 = d[0] * d[0] + d[1] * d[1] + d[2] * d[2] + eps2;
+                let f = g / (r2 * r2.sqrt());
+
+                for ((ak, ajk), dk) in a.iter_mut().zip(aj.iter_mut()).zip(d) {
+                    *ak += p.m[j] * f * dk;
+                    *ajk -= mi * f * dk;
+                }
+            }
+            for k in 0..3 {
+                acc[i][k] += a[k];
+            }
+        }
+        acc
+    }).collect();
+
+    let mut acc = vec![[0.0, 0.0, 0.0]; n];
+    for part in &partial {
+        for i in 0..n {
+            for k in 0..3 {
+                acc[i][k] += part[i][k];
+            }
+        }
+    }
+    for (a, fixed) in acc.iter_mut().zip(&p.fixed) {
+        if *fixed {
+            *a = [0.0, 0.0, 0.0];
+        }
+    }
+    acc
+}
+
+// max deviation from the serial kernel relative to the rms acceleration
+fn deviation(a: &[[f64; 3]], b: &[[f64; 3]]) -> f64 {
+    let mut max_err: f64 = 0.0;
+    let mut norm = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        let mut d = 0.0;
+        for k in 0..3 {
+            d += (x[k] - y[k]) * (x[k] - y[k]);
+            norm += x[k] * x[k];
+        }
+        max_err = max_err.max(d);
+    }
+    f64::sqrt(max_err / norm * a.len() as f64)
+}
+
+pub fn run_test() {
+    // ring around a heavy body, like saturn.txt
+    let mut p = Particles::new();
+    p.push([0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 1e5);
+    for i in 0..1500 {
+        let phi = 2.0 * std::f64::consts::PI * (i as f64) * 0.618034;
+        let r = 0.8 + 0.3 * ((i * 7919) % 1500) as f64 / 1500.0;
+        p.push([r * phi.cos(), r * phi.sin(), 1e-3 * phi.sin()], [0.0, 0.0, 0.0], 1e-3);
+    }
+    p.fixed[1] = true;
+
+    let mut q = p.clone();
+    direct(&mut q, 1.0, 0.0);
+    let serial: Vec<[f64; 3]> = (0..q.len()).map(|i| q.a(i)).collect();
+    let mut results = Vec::new();
+    for threads in [1, 2, 4] {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        let first = pool.install(|| direct_symmetric(&p, 1.0, 0.0));
+        let second = pool.install(|| direct_symmetric(&p, 1.0, 0.0));
+        if first != second {
+            println!("Error1"); exit(1);
+        }
+        results.push(first);
+    }
+
+    for (threads, acc) in [1, 2, 4].iter().zip(&results) {
+        let err = deviation(&serial, acc);
+        println!("threads {threads}: {err}");
+        if err > 1e-13 {
+            println!("Error2"); exit(1);
+        }
+        if acc[1] != [0.0, 0.0, 0.0] {
+            println!("Error3"); exit(1);
+        }
+    }
+
+    // one buffer per thread at most
+    for threads in [1, 2, 3, 4, 7, 16] {
+        for n in [0, 1, 2, 5, 100, 1501] {
+            if chunks(n, threads).len() > threads {
+                println!("Error4"); exit(1);
+            }
+        }
+    }
+    println!("Ok");
+}
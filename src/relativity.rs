@@ -0,0 +1,114 @@
+use std::process::exit;
+
+use crate::body::{Body, Particles, System};
+use crate::integrator::Integrator;
+use crate::ias15::Ias15;
+use crate::units::{Units, AU, MASSES};
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+// first post-Newtonian correction of the most massive body's field, test
+// particle limit of the Schwarzschild metric in harmonic coordinates:
+// a = mu / (c^2 r^3) ((4 mu / r - v^2) r + 4 (r.v) v), r and v relative
+// to that body; the body takes the reaction, so momentum is kept
+pub fn add_relativity(p: &mut Particles, g: f64, c: f64) {
+    if c <= 0.0 || p.len() < 2 {
+        return;
+    }
+    let mut central = 0;
+    for i in 0..p.len() {
+        if p.m[i] > p.m[central] {
+            central = i;
+        }
+    }
+    let (rc, vc) = (p.r(central), p.v(central));
+    let mu = g * p.m[central];
+    let c2 = c * c;
+    let mut reaction = [0.0, 0.0, 0.0];
+    for i in 0..p.len() {
+        if i == central { continue; }
+        let (ri, vi) = (p.r(i), p.v(i));
+        let r = [ri[0] - rc[0], ri[1] - rc[1], ri[2] - rc[2]];
+        let v = [vi[0] - vc[0], vi[1] - vc[1], vi[2] - vc[2]];
+        let d = f64::sqrt(dot(r, r));
+        let f = mu / (c2 * d * d * d);
+        let fr = 4.0 * mu / d - dot(v, v);
+        let fv = 4.0 * dot(r, v);
+        let a = [
+            f * (fr * r[0] + fv * v[0]),
+            f * (fr * r[1] + fv * v[1]),
+            f * (fr * r[2] + fv * v[2])
+        ];
+        for k in 0..3 {
+            reaction[k] -= p.m[i] * a[k] / p.m[central];
+        }
+        if p.fixed[i] { continue; }
+        p.ax[i] += a[0];
+        p.ay[i] += a[1];
+        p.az[i] += a[2];
+    }
+    if !p.fixed[central] {
+        p.ax[central] += reaction[0];
+        p.ay[central] += reaction[1];
+        p.az[central] += reaction[2];
+    }
+}
+
+// direction of the Laplace-Runge-Lenz vector of body 1 around body 0,
+// in the orbital plane z = 0
+fn perihelion(data: &System) -> f64 {
+    let p = &data.particles;
+    let mu = data.g * (p.m[0] + p.m[1]);
+    let (r0, r1, v0, v1) = (p.r(0), p.r(1), p.v(0), p.v(1));
+    let r = [r1[0] - r0[0], r1[1] - r0[1], r1[2] - r0[2]];
+    let v = [v1[0] - v0[0], v1[1] - v0[1], v1[2] - v0[2]];
+    let d = f64::sqrt(dot(r, r));
+    let h = r[0] * v[1] - r[1] * v[0];
+    // e = v x h / mu - r / |r| with h along z
+    let ex = v[1] * h / mu - r[0] / d;
+    let ey = -v[0] * h / mu - r[1] / d;
+    f64::atan2(ey, ex)
+}
+
+// Sun and Mercury as in solar.txt: AU, Earth masses and G = 2.92e-6,
+// c in the unit of speed that follows from them
+pub fn run_test() {
+    let g = 2.92e-6;
+    let (ms, mm) = (333333.0, 0.038);
+    let earth = MASSES.iter().find(|m| m.0 == "earth").unwrap().1;
+    let units = Units::with_g(AU, earth, g);
+    let c = 299792458.0 * units.time / units.length;
+    let (a, e) = (0.387098, 0.205630);
+    let mu = g * (ms + mm);
+
+    let mut data = System::new(0.0);
+    data.g = g;
+    data.forces.light_speed = c;
+    let rp = a * (1.0 - e);
+    let vp = f64::sqrt(mu * (1.0 + e) / rp);
+    data.add(Body::new("Sun"), [0.0, 0.0, 0.0], [0.0, -mm / ms * vp, 0.0], ms);
+    data.add(Body::new("Mercury"), [rp, 0.0, 0.0], [0.0, vp, 0.0], mm);
+
+    let period = 2.0 * std::f64::consts::PI * f64::sqrt(a * a * a / mu);
+    let orbits = 20;
+    data.dt = period;
+    let mut method = Ias15::new(1e-9);
+    let w0 = perihelion(&data);
+    method.init(&mut data);
+    for _ in 0..orbits {
+        method.step(&mut data);
+    }
+    let measured = (perihelion(&data) - w0) / orbits as f64;
+    let expected = 6.0 * std::f64::consts::PI * mu / (c * c * a * (1.0 - e * e));
+
+    // a century is 100 orbits of the Earth
+    let year = 2.0 * std::f64::consts::PI / f64::sqrt(g * ms);
+    let per_century = 100.0 * year / period * 180.0 / std::f64::consts::PI * 3600.0;
+    println!("perihelion advance {} {} arcsec/century, c = {c}", measured * per_century, expected * per_century);
+    if f64::abs(measured / expected - 1.0) > 1e-3 {
+        println!("Error1"); exit(1);
+    }
+    println!("Ok");
+}
@@ -0,0 +1,90 @@
+use crate::body::{Particles, System};
+use crate::gravity::accelerations;
+use crate::integrator::Integrator;
+
+// classic fourth-order Runge-Kutta
+pub struct Rk4 {
+    // particles at the intermediate stages
+    stage: Particles
+}
+
+impl Rk4 {
+    pub fn new() -> Rk4 {
+        Rk4 { stage: Particles::new() }
+    }
+}
+
+impl Default for Rk4 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Integrator for Rk4 {
+    fn name(&self) -> &'static str {
+        "rk4"
+    }
+
+    fn order(&self) -> u32 {
+        4
+    }
+
+    fn init(&mut self, _data: &mut System) { }
+
+    fn step(&mut self, data: &mut System) {
+        let c = [0.0, 0.5, 0.5, 1.0];
+        let w = [1.0 / 6.0, 1.0 / 3.0, 1.0 / 3.0, 1.0 / 6.0];
+        let n = data.particles.len();
+        let dt = data.dt;
+
+        // derivatives of r and v at the previous stage
+        let mut kr = vec![[0.0, 0.0, 0.0]; n];
+        let mut kv = vec![[0.0, 0.0, 0.0]; n];
+        // weighted sums of the stage derivatives
+        let mut dr = vec![[0.0, 0.0, 0.0]; n];
+        let mut dv = vec![[0.0, 0.0, 0.0]; n];
+
+        let p = &mut data.particles;
+        self.stage.clone_from(p);
+        for s in 0..4 {
+            for i in 0..n {
+                let (r, v) = (p.r(i), p.v(i));
+                let mut sr = [0.0, 0.0, 0.0];
+                let mut sv = [0.0, 0.0, 0.0];
+                for k in 0..3 {
+                    sr[k] = r[k] + c[s] * dt * kr[i][k];
+                    sv[k] = v[k] + c[s] * dt * kv[i][k];
+                }
+                self.stage.set_r(i, sr);
+                self.stage.set_v(i, sv);
+            }
+
+            accelerations(&mut self.stage, data.g, &data.forces);
+            if s == 0 {
+                p.ax.clone_from(&self.stage.ax);
+                p.ay.clone_from(&self.stage.ay);
+                p.az.clone_from(&self.stage.az);
+            }
+
+            for i in 0..n {
+                // fixed bodies do not move
+                kr[i] = if p.fixed[i] { [0.0, 0.0, 0.0] } else { self.stage.v(i) };
+                kv[i] = self.stage.a(i);
+                for k in 0..3 {
+                    dr[i][k] += w[s] * kr[i][k];
+                    dv[i][k] += w[s] * kv[i][k];
+                }
+            }
+        }
+
+        for i in 0..n {
+            let (mut r, mut v) = (p.r(i), p.v(i));
+            for k in 0..3 {
+                r[k] += dt * dr[i][k];
+                v[k] += dt * dv[i][k];
+            }
+            p.set_r(i, r);
+            p.set_v(i, v);
+        }
+    }
+}
@@ -0,0 +1,538 @@
+use std::process::exit;
+
+use serde::{Deserialize, Serialize};
+
+use crate::body::{Body, System};
+use crate::constraint::{Constraint, Kind};
+use crate::field::Field;
+use crate::nongrav::NonGrav;
+use crate::elements::{Elements, conic_error, state_at, relative};
+use crate::load::{LoadError, LoadErrorKind, Settings};
+use crate::units::{Scale, Units, rescale_time, LENGTHS, MASSES, TIMES, DAY};
+
+/*
+  self-describing scenario, TOML or JSON with the same keys:
+
+  G = 2.92e-6
+  units = { length = "au", mass = "earth" }
+  dt = 0.005              optional, like softening, c (speed of light),
+  T = 10                  T (simulated time) and method
+  method = "verlet"
+  fields = [{ kind = "uniform", g = [0, 0, -1] }, { kind = "harmonic", center = [0, 0, 0], k = 1 }]
+
+  [[bodies]]
+  name = "Sun"
+  mass = 333333
+  r = [0, 0, 0]           r and v default to 0, an orbit replaces them
+  v = [0, 0, 0]
+  fixed = true            optional, like color, rad (size on screen),
+  color = "ffff00"        radius (physical size), j2, drag and radiation
+  j2 = { j2 = 1e-7, radius = 0.005, axis = [0, 0, 1] }
+
+  [[bodies]]
+  name = "Earth"
+  mass = 1
+  orbit = { parent = "Sun", a = 1, e = 0.0167, i = 0, node = 0, peri = 102.9, nu = 0 }
+  drag = { law = "linear", k = 0.1, medium = "Sun" }
+  radiation = { beta = 0.1, source = "Sun" }
+  constraints = [{ kind = "wall", center = [0, 0, 0], min_rad = 0.5, max_rad = 2 }]
+
+  orbit angles are in degrees, a body can only orbit one listed before it;
+  a > 0 and e < 1 on an ellipse, a < 0 and e > 1 on a hyperbola;
+  constraints are walls, springs (with k) or rattle (the sphere of max_rad)
+
+  units are optional, a system "si", "astro" (au, sun, day) or "nbody",
+  or a table of length, mass and time by name or in SI units, see units.rs;
+  with a unit of time G follows from the units and everything is
+  converted to the internal units, where G = 1 with the same length and
+  mass; without one G is required and gives the unit of time
+ */
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(rename = "G", default, skip_serializing_if = "Option::is_none")]
+    pub g: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub units: Option<UnitsSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dt: Option<f64>,
+    #[serde(rename = "T", default, skip_serializing_if = "Option::is_none")]
+    pub max_time: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub softening: f64,
+    #[serde(rename = "c", default, skip_serializing_if = "is_zero")]
+    pub light_speed: f64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldSpec>,
+    pub bodies: Vec<BodySpec>
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BodySpec {
+    pub name: String,
+    pub mass: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub r: Option<[f64; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v: Option<[f64; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orbit: Option<OrbitSpec>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub fixed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rad: Option<f64>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub radius: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub j2: Option<J2Spec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drag: Option<DragSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radiation: Option<RadiationSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub constraints: Vec<ConstraintSpec>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum UnitsSpec {
+    System(String),
+    Table(UnitsTable)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct UnitsTable {
+    pub length: Scale,
+    pub mass: Scale,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<Scale>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct OrbitSpec {
+    pub parent: String,
+    pub a: f64,
+    #[serde(default)]
+    pub e: f64,
+    #[serde(default)]
+    pub i: f64,
+    #[serde(default)]
+    pub node: f64,
+    #[serde(default)]
+    pub peri: f64,
+    #[serde(default)]
+    pub nu: f64
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct J2Spec {
+    pub j2: f64,
+    pub radius: f64,
+    #[serde(default = "z_axis")]
+    pub axis: [f64; 3]
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum DragLaw {
+    Linear,
+    Quadratic
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DragSpec {
+    pub law: DragLaw,
+    pub k: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub medium: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RadiationSpec {
+    pub beta: f64,
+    pub source: String
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum ConstraintSpec {
+    Wall {
+        #[serde(default)]
+        center: [f64; 3],
+        #[serde(default)]
+        min_rad: f64,
+        #[serde(default)]
+        max_rad: f64
+    },
+    Spring {
+        #[serde(default)]
+        center: [f64; 3],
+        #[serde(default)]
+        min_rad: f64,
+        #[serde(default)]
+        max_rad: f64,
+        k: f64
+    },
+    Rattle {
+        #[serde(default)]
+        center: [f64; 3],
+        max_rad: f64
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum FieldSpec {
+    Uniform { g: [f64; 3] },
+    Harmonic {
+        #[serde(default)]
+        center: [f64; 3],
+        k: f64
+    }
+}
+
+fn is_zero(x: &f64) -> bool {
+    *x == 0.0
+}
+
+fn is_false(x: &bool) -> bool {
+    !*x
+}
+
+fn z_axis() -> [f64; 3] {
+    [0.0, 0.0, 1.0]
+}
+
+// 1-based line and column of a byte offset
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap().chars().count() + 1;
+    (line, column)
+}
+
+impl Scenario {
+    pub fn from_toml(file: &str, text: &str) -> Result<Scenario, LoadError> {
+        toml::from_str(text).map_err(|e| {
+            let (line, column) = e.span().map_or((0, 0), |s| position(text, s.start));
+            LoadError { file : String::from(file), line : line, column : column, kind : LoadErrorKind::Syntax(String::from(e.message())) }
+        })
+    }
+
+    pub fn from_json(file: &str, text: &str) -> Result<Scenario, LoadError> {
+        serde_json::from_str(text).map_err(|e| {
+            // serde_json puts the position into the message as well
+            let message = e.to_string();
+            let message = match message.rfind(" at line ") {
+                Some(k) => String::from(&message[..k]),
+                None => message
+            };
+            LoadError { file : String::from(file), line : e.line(), column : e.column(), kind : LoadErrorKind::Syntax(message) }
+        })
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).unwrap()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    // the internal units, G in the units of the file and the internal
+    // unit of time in the declared one, None when there is nothing to convert
+    fn units(&self) -> Result<(Option<Units>, f64, Option<f64>), LoadErrorKind> {
+        let value = |what, scale: &Scale, table| scale.value(table)
+            .ok_or_else(|| LoadErrorKind::BadValue(what, scale.to_string()));
+        let scales = match &self.units {
+            None => None,
+            Some(UnitsSpec::System(name)) => match Units::system(name) {
+                Some(units) => units.map(|u| (u.length, u.mass, Some(u.time))),
+                None => return Err(LoadErrorKind::BadValue("unit system", name.clone()))
+            },
+            Some(UnitsSpec::Table(t)) => {
+                let time = match &t.time {
+                    Some(scale) => Some(value("unit of time", scale, TIMES)?),
+                    None => None
+                };
+                Some((value("unit of length", &t.length, LENGTHS)?, value("unit of mass", &t.mass, MASSES)?, time))
+            }
+        };
+        match (scales, self.g) {
+            (None, Some(g)) => Ok((None, g, None)),
+            (None, None) => Err(LoadErrorKind::Missing("G")),
+            (Some((length, mass, None)), Some(g)) => Ok((Some(Units::with_g(length, mass, g)), g, None)),
+            (Some((_, _, None)), None) => Err(LoadErrorKind::Missing("G or a unit of time")),
+            (Some((length, mass, Some(time))), None) => {
+                let declared = Units { length : length, mass : mass, time : time };
+                let internal = declared.internal();
+                Ok((Some(internal), declared.g(), Some(internal.time / time)))
+            }
+            (Some((_, _, Some(_))), Some(_)) => Err(LoadErrorKind::Syntax(String::from("G follows from the unit of time, give only one of them")))
+        }
+    }
+
+    // fills data, bodies refer to each other by name
+    pub fn apply(&self, data: &mut System, file: &str) -> Result<Settings, LoadError> {
+        let error = |kind| LoadError { file : String::from(file), line : 0, column : 0, kind : kind };
+        let find = |data: &System, name: &str| data.bodies.iter().position(|b| b.name == name)
+            .ok_or_else(|| error(LoadErrorKind::UnknownBody(String::from(name))));
+
+        let (units, g, convert) = self.units().map_err(error)?;
+        data.g = g;
+        data.units = units;
+        data.forces.softening = self.softening;
+        data.forces.light_speed = self.light_speed;
+        for b in &self.bodies {
+            let (mut r, mut v) = (b.r.unwrap_or([0.0; 3]), b.v.unwrap_or([0.0; 3]));
+            // an orbit replaces r and v
+            if let Some(o) = &b.orbit {
+                let j = find(data, &o.parent)?;
+                if let Some((k, what)) = conic_error(o.a, o.e) {
+                    return Err(error(LoadErrorKind::BadValue(what, [o.a, o.e][k].to_string())));
+                }
+                let el = Elements {
+                    a : o.a, e : o.e, i : f64::to_radians(o.i), node : f64::to_radians(o.node), peri : f64::to_radians(o.peri),
+                    mean_anomaly : 0.0, period : 0.0
+                };
+                let (ro, vo) = state_at(&el, f64::to_radians(o.nu), data.g * (data.particles.m[j] + b.mass));
+                let (rp, vp) = (data.particles.r(j), data.particles.v(j));
+                for k in 0..3 {
+                    r[k] = rp[k] + ro[k];
+                    v[k] = vp[k] + vo[k];
+                }
+            }
+            let mut body = Body::new(&b.name);
+            if let Some(color) = &b.color {
+                body.color = color.clone();
+            }
+            if let Some(rad) = b.rad {
+                body.rad = rad;
+            }
+            data.add(body, r, v, b.mass);
+            let i = data.particles.len() - 1;
+            data.particles.fixed[i] = b.fixed;
+            data.particles.radius[i] = b.radius;
+        }
+
+        // the second pass can refer to bodies listed later
+        for (i, b) in self.bodies.iter().enumerate() {
+            if let Some(j2) = &b.j2 {
+                let a = j2.axis;
+                let norm = f64::sqrt(a[0] * a[0] + a[1] * a[1] + a[2] * a[2]);
+                if !norm.is_finite() || norm == 0.0 {
+                    return Err(error(LoadErrorKind::BadValue("axis", format!("{a:?}"))));
+                }
+                data.forces.fields.push(Field::J2 { body : i, j2 : j2.j2, radius : j2.radius, axis : [a[0] / norm, a[1] / norm, a[2] / norm] });
+            }
+            if let Some(drag) = &b.drag {
+                let medium = match &drag.medium {
+                    Some(name) => Some(find(data, name)?),
+                    None => None
+                };
+                data.forces.nongrav.push(NonGrav::Drag { body : i, k : drag.k, quadratic : drag.law == DragLaw::Quadratic, medium : medium });
+            }
+            if let Some(rad) = &b.radiation {
+                let source = find(data, &rad.source)?;
+                // the light would come from r = 0
+                if source == i {
+                    return Err(error(LoadErrorKind::BadValue("radiation source, not the body itself", rad.source.clone())));
+                }
+                data.forces.nongrav.push(NonGrav::Radiation { body : i, beta : rad.beta, source : source });
+            }
+            for c in &b.constraints {
+                data.forces.constraints.push(match *c {
+                    ConstraintSpec::Wall { center, min_rad, max_rad } => Constraint::wall(i, center, min_rad, max_rad),
+                    ConstraintSpec::Spring { center, min_rad, max_rad, k } => Constraint::spring(i, center, min_rad, max_rad, k),
+                    ConstraintSpec::Rattle { center, max_rad } => Constraint::rattle(i, center, max_rad)
+                });
+            }
+        }
+        for f in &self.fields {
+            data.forces.fields.push(match *f {
+                FieldSpec::Uniform { g } => Field::Uniform { g : g },
+                FieldSpec::Harmonic { center, k } => Field::Harmonic { center : center, k : k }
+            });
+        }
+        let mut settings = Settings { dt : self.dt, max_time : self.max_time, method : self.method.clone() };
+        if let Some(s) = convert {
+            rescale_time(data, s);
+            // exactly, not up to roundoff
+            data.g = 1.0;
+            settings.dt = settings.dt.map(|dt| dt / s);
+            settings.max_time = settings.max_time.map(|t| t / s);
+        }
+        Ok(settings)
+    }
+
+    // the scenario of a loaded system, bodies are given by r and v
+    pub fn from_system(data: &System, settings: &Settings) -> Scenario {
+        let p = &data.particles;
+        let mut bodies: Vec<BodySpec> = data.bodies.iter().enumerate().map(|(i, b)| BodySpec {
+            name : b.name.clone(),
+            mass : p.m[i],
+            r : Some(p.r(i)),
+            v : Some(p.v(i)),
+            fixed : p.fixed[i],
+            color : Some(b.color.clone()),
+            rad : Some(b.rad),
+            radius : p.radius[i],
+            ..BodySpec::default()
+        }).collect();
+        let mut fields = Vec::new();
+        for f in &data.forces.fields {
+            match *f {
+                Field::J2 { body, j2, radius, axis } => bodies[body].j2 = Some(J2Spec { j2 : j2, radius : radius, axis : axis }),
+                Field::Uniform { g } => fields.push(FieldSpec::Uniform { g : g }),
+                Field::Harmonic { center, k } => fields.push(FieldSpec::Harmonic { center : center, k : k })
+            }
+        }
+        for f in &data.forces.nongrav {
+            match *f {
+                NonGrav::Drag { body, k, quadratic, medium } => bodies[body].drag = Some(DragSpec {
+                    law : if quadratic { DragLaw::Quadratic } else { DragLaw::Linear },
+                    k : k,
+                    medium : medium.map(|j| data.bodies[j].name.clone())
+                }),
+                NonGrav::Radiation { body, beta, source } => bodies[body].radiation = Some(RadiationSpec {
+                    beta : beta,
+                    source : data.bodies[source].name.clone()
+                })
+            }
+        }
+        for c in &data.forces.constraints {
+            bodies[c.body].constraints.push(match c.kind {
+                Kind::Wall => ConstraintSpec::Wall { center : c.center, min_rad : c.min_rad, max_rad : c.max_rad },
+                Kind::Spring(k) => ConstraintSpec::Spring { center : c.center, min_rad : c.min_rad, max_rad : c.max_rad, k : k },
+                Kind::Rattle => ConstraintSpec::Rattle { center : c.center, max_rad : c.max_rad }
+            });
+        }
+        Scenario {
+            g : Some(data.g),
+            units : data.units.map(|u| UnitsSpec::Table(UnitsTable { length : Scale::Value(u.length), mass : Scale::Value(u.mass), time : None })),
+            dt : settings.dt,
+            max_time : settings.max_time,
+            method : settings.method.clone(),
+            softening : data.forces.softening,
+            light_speed : data.forces.light_speed,
+            fields : fields,
+            bodies : bodies
+        }
+    }
+}
+
+const EXAMPLE: &str = r#"
+G = 1
+T = 5
+method = "yoshida4"
+fields = [{ kind = "uniform", g = [0, 0, -0.01] }]
+
+[[bodies]]
+name = "star"
+mass = 1
+fixed = true
+color = "ffff00"
+
+[[bodies]]
+name = "planet"
+mass = 1e-3
+orbit = { parent = "star", a = 1, e = 0.1, i = 10, nu = 90 }
+drag = { law = "quadratic", k = 0.01, medium = "star" }
+constraints = [{ kind = "spring", max_rad = 2, k = 10 }]
+"#;
+
+pub fn run_test() {
+    let scenario = match Scenario::from_toml("example.toml", EXAMPLE) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("{e}");
+            println!("Error1"); exit(1);
+        }
+    };
+    let mut data = System::new(0.001);
+    let settings = scenario.apply(&mut data, "example.toml").unwrap();
+    let p = &data.particles;
+    println!("scenario: {:?} {:?} {:?}", settings.method, p.r(1), p.v(1));
+    // pericenter on the x axis, at nu = 90 the distance is the semi-latus rectum
+    if settings.max_time != Some(5.0) || settings.dt.is_some() || !p.fixed[0]
+        || f64::abs(p.r(1)[1] - 0.99 * f64::to_radians(10.0).cos()) > 1e-15
+        || data.forces.nongrav.len() != 1 || data.forces.constraints.len() != 1 || data.forces.fields.len() != 1 {
+        println!("Error2"); exit(1);
+    }
+
+    // TOML -> system -> JSON -> system keeps everything
+    let json = Scenario::from_system(&data, &settings).to_json();
+    let mut copy = System::new(0.001);
+    let copy_settings = Scenario::from_json("copy.json", &json).unwrap().apply(&mut copy, "copy.json").unwrap();
+    let q = &copy.particles;
+    if q.x != p.x || q.vy != p.vy || q.fixed != p.fixed || copy.bodies[0].color != "ffff00"
+        || copy_settings.method != settings.method
+        || format!("{:?}", copy.forces.nongrav) != format!("{:?}", data.forces.nongrav)
+        || format!("{:?}", copy.forces.constraints) != format!("{:?}", data.forces.constraints) {
+        println!("{json}");
+        println!("Error3"); exit(1);
+    }
+
+    // SI in, G = 1 with meters and kilograms inside; a year of the Earth
+    let si = "units = \"si\"\nT = 31557600\n[[bodies]]\nname = \"Sun\"\nmass = 1.98847e30\n\
+              [[bodies]]\nname = \"Earth\"\nmass = 5.9722e24\norbit = { parent = \"Sun\", a = 1.495978707e11 }\n";
+    let mut data = System::new(0.001);
+    let settings = Scenario::from_toml("si.toml", si).unwrap().apply(&mut data, "si.toml").unwrap();
+    let units = data.units.unwrap();
+    let speed = data.particles.v(1)[1] * units.length / units.time;
+    let period = relative(&data.particles, data.g, 0, 1).unwrap().period * units.time / DAY;
+    println!("si: {:?} {speed} m/s {period} days", units);
+    if data.g != 1.0 || f64::abs(settings.max_time.unwrap() * units.time / 31557600.0 - 1.0) > 1e-14
+        || f64::abs(speed - 29785.0) > 5.0 || f64::abs(period - 365.25) > 0.1 {
+        println!("Error4"); exit(1);
+    }
+    let e = Scenario::from_toml("si.toml", &format!("G = 1\n{si}")).unwrap().apply(&mut System::new(0.001), "si.toml").unwrap_err();
+    println!("{e}");
+    if !matches!(e.kind, LoadErrorKind::Syntax(_)) {
+        println!("Error5"); exit(1);
+    }
+
+    // errors point into the text
+    let e = Scenario::from_toml("bad.toml", "G = 1\n[[bodies]]\nname = \"a\"\nmass = \"x\"\n").unwrap_err();
+    println!("{e}");
+    if e.line != 4 || e.column != 8 {
+        println!("Error6"); exit(1);
+    }
+    let e = Scenario::from_json("bad.json", "{\"G\": 1,\n \"bodies\": [{\"name\": \"a\", \"mas\": 1}]}").unwrap_err();
+    println!("{e}");
+    if e.line != 2 {
+        println!("Error7"); exit(1);
+    }
+
+    // an ellipse needs e < 1
+    let bad = "G = 1\n[[bodies]]\nname = \"a\"\nmass = 1\n[[bodies]]\nname = \"b\"\nmass = 1\norbit = { parent = \"a\", a = 1, e = 1.5 }\n";
+    let e = Scenario::from_toml("bad.toml", bad).unwrap().apply(&mut System::new(0.001), "bad.toml").unwrap_err();
+    println!("{e}");
+    if !matches!(e.kind, LoadErrorKind::BadValue(..)) {
+        println!("Error8"); exit(1);
+    }
+    let bad = "G = 1\n[[bodies]]\nname = \"a\"\nmass = 1\nj2 = { j2 = 0.01, radius = 0.1, axis = [0, 0, 0] }\n";
+    let e = Scenario::from_toml("bad.toml", bad).unwrap().apply(&mut System::new(0.001), "bad.toml").unwrap_err();
+    println!("{e}");
+    if !matches!(e.kind, LoadErrorKind::BadValue("axis", _)) {
+        println!("Error9"); exit(1);
+    }
+    let bad = "G = 1\n[[bodies]]\nname = \"a\"\nmass = 1\nradiation = { beta = 0.1, source = \"a\" }\n";
+    let e = Scenario::from_toml("bad.toml", bad).unwrap().apply(&mut System::new(0.001), "bad.toml").unwrap_err();
+    println!("{e}");
+    if !matches!(e.kind, LoadErrorKind::BadValue(..)) {
+        println!("Error10"); exit(1);
+    }
+    println!("Ok");
+}
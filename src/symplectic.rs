@@ -0,0 +1,103 @@
+use crate::body::System;
+use crate::gravity::accelerations;
+use crate::integrator::Integrator;
+use crate::verlet::{verlet_init, verlet_next, kick, drift, last_kick};
+
+// symmetric composition of verlet_next steps with weights w[i] * dt
+pub struct Composition {
+    name: &'static str,
+    order: u32,
+    w: Vec<f64>
+}
+
+impl Composition {
+    // Yoshida triple jump, 4th order
+    pub fn yoshida4() -> Composition {
+        let cbrt2 = f64::cbrt(2.0);
+        let w1 = 1.0 / (2.0 - cbrt2);
+        let w0 = -cbrt2 / (2.0 - cbrt2);
+        Composition {
+            name : "yoshida4",
+            order : 4,
+            w : vec![w1, w0, w1]
+        }
+    }
+
+    // Yoshida 6th order, solution A
+    pub fn yoshida6() -> Composition {
+        let w1 = -1.17767998417887;
+        let w2 = 0.235573213359357;
+        let w3 = 0.784513610477560;
+        let w0 = 1.0 - 2.0 * (w1 + w2 + w3);
+        Composition {
+            name : "yoshida6",
+            order : 6,
+            w : vec![w3, w2, w1, w0, w1, w2, w3]
+        }
+    }
+}
+
+impl Integrator for Composition {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn order(&self) -> u32 {
+        self.order
+    }
+
+    // every substep is a verlet step
+    fn handles_constraints(&self) -> bool {
+        true
+    }
+
+    fn init(&mut self, data: &mut System) {
+        verlet_init(data);
+    }
+
+    fn step(&mut self, data: &mut System) {
+        let dt = data.dt;
+        for w in &self.w {
+            verlet_next(data, w * dt);
+        }
+    }
+}
+
+// Forest-Ruth 4th order in drift-kick-drift form
+pub struct ForestRuth;
+
+// velocity dependent forces get the symmetric trapezoidal kick
+fn force_kick(data: &mut System, h: f64) {
+    accelerations(&mut data.particles, data.g, &data.forces);
+    if data.forces.velocity_dependent() {
+        kick(&mut data.particles, 0.5 * h);
+        last_kick(&mut data.particles, data.g, &data.forces, 0.5 * h);
+    } else {
+        kick(&mut data.particles, h);
+    }
+}
+
+impl Integrator for ForestRuth {
+    fn name(&self) -> &'static str {
+        "forest-ruth"
+    }
+
+    fn order(&self) -> u32 {
+        4
+    }
+
+    fn init(&mut self, _data: &mut System) { }
+
+    fn step(&mut self, data: &mut System) {
+        let theta = 1.0 / (2.0 - f64::cbrt(2.0));
+        let dt = data.dt;
+
+        drift(&mut data.particles, 0.5 * theta * dt);
+        force_kick(data, theta * dt);
+        drift(&mut data.particles, 0.5 * (1.0 - theta) * dt);
+        force_kick(data, (1.0 - 2.0 * theta) * dt);
+        drift(&mut data.particles, 0.5 * (1.0 - theta) * dt);
+        force_kick(data, theta * dt);
+        drift(&mut data.particles, 0.5 * theta * dt);
+    }
+}
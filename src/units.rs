@@ -0,0 +1,179 @@
+use std::fmt;
+use std::process::exit;
+
+use serde::{Deserialize, Serialize};
+
+use crate::body::System;
+use crate::constraint::Kind;
+use crate::field::Field;
+use crate::nongrav::NonGrav;
+
+// the units of length, mass and time in m, kg and s
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Units {
+    pub length: f64,
+    pub mass: f64,
+    pub time: f64
+}
+
+// CODATA 2018
+pub const G_SI: f64 = 6.67430e-11;
+pub const AU: f64 = 1.495978707e11;
+pub const DAY: f64 = 86400.0;
+
+pub const LENGTHS: &[(&str, f64)] = &[
+    ("m", 1.0),
+    ("km", 1e3),
+    ("au", AU),
+    ("pc", 3.085677581491367e16),
+];
+
+pub const MASSES: &[(&str, f64)] = &[
+    ("kg", 1.0),
+    ("sun", 1.98847e30),
+    ("earth", 5.9722e24),
+    ("jupiter", 1.89813e27),
+];
+
+pub const TIMES: &[(&str, f64)] = &[
+    ("s", 1.0),
+    ("day", DAY),
+    ("year", 365.25 * DAY),
+];
+
+// names of the units of length, mass and time
+pub type UnitNames = (&'static str, &'static str, &'static str);
+
+// named systems of length, mass and time; nbody is nondimensional,
+// the same as declaring no units
+pub const SYSTEMS: &[(&str, Option<UnitNames>)] = &[
+    ("si", Some(("m", "kg", "s"))),
+    ("astro", Some(("au", "sun", "day"))),
+    ("nbody", None),
+];
+
+// a unit by name from one of the tables above, or a number of SI units
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Scale {
+    Value(f64),
+    Name(String)
+}
+
+impl Scale {
+    pub fn parse(token: &str) -> Scale {
+        match token.parse::<f64>() {
+            Ok(x) => Scale::Value(x),
+            Err(_) => Scale::Name(String::from(token))
+        }
+    }
+
+    // None for an unknown name or a scale that is not positive
+    pub fn value(&self, table: &[(&str, f64)]) -> Option<f64> {
+        let x = match self {
+            Scale::Value(x) => *x,
+            Scale::Name(name) => table.iter().find(|u| u.0 == name).map(|u| u.1)?
+        };
+        if x > 0.0 { Some(x) } else { None }
+    }
+}
+
+impl fmt::Display for Scale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Scale::Value(x) => write!(f, "{x}"),
+            Scale::Name(name) => write!(f, "{name}")
+        }
+    }
+}
+
+impl Units {
+    pub fn by_name(length: &str, mass: &str, time: &str) -> Option<Units> {
+        Some(Units {
+            length : Scale::parse(length).value(LENGTHS)?,
+            mass : Scale::parse(mass).value(MASSES)?,
+            time : Scale::parse(time).value(TIMES)?
+        })
+    }
+
+    // the named system, Some(None) for nbody
+    pub fn system(name: &str) -> Option<Option<Units>> {
+        let s = SYSTEMS.iter().find(|s| s.0 == name)?;
+        Some(s.1.map(|(l, m, t)| Units::by_name(l, m, t).unwrap()))
+    }
+
+    // the unit of time that makes the gravitational constant g
+    pub fn with_g(length: f64, mass: f64, g: f64) -> Units {
+        Units { length : length, mass : mass, time : f64::sqrt(g * length * length * length / (G_SI * mass)) }
+    }
+
+    // the gravitational constant in these units
+    pub fn g(&self) -> f64 {
+        G_SI * self.mass * self.time * self.time / (self.length * self.length * self.length)
+    }
+
+    // the internal units: the same length and mass, G = 1
+    pub fn internal(&self) -> Units {
+        Units::with_g(self.length, self.mass, 1.0)
+    }
+}
+
+// changes the unit of time of the whole system to s old units, lengths
+// and masses stay; dt and the simulated time are up to the caller
+pub fn rescale_time(data: &mut System, s: f64) {
+    let p = &mut data.particles;
+    for i in 0..p.len() {
+        p.vx[i] *= s;
+        p.vy[i] *= s;
+        p.vz[i] *= s;
+    }
+    data.g *= s * s;
+    let f = &mut data.forces;
+    f.light_speed *= s;
+    for c in f.constraints.iter_mut() {
+        if let Kind::Spring(k) = &mut c.kind {
+            *k *= s * s;
+        }
+    }
+    for field in f.fields.iter_mut() {
+        match field {
+            Field::Uniform { g } => {
+                for x in g.iter_mut() {
+                    *x *= s * s;
+                }
+            }
+            Field::Harmonic { k, .. } => *k *= s * s,
+            // J2 is dimensionless
+            Field::J2 { .. } => {}
+        }
+    }
+    for force in f.nongrav.iter_mut() {
+        // quadratic drag goes with 1 / length, beta is dimensionless
+        if let NonGrav::Drag { k, quadratic : false, .. } = force {
+            *k *= s;
+        }
+    }
+}
+
+pub fn run_test() {
+    // G in AU, solar masses and days is the Gaussian constant squared
+    let astro = Units::system("astro").unwrap().unwrap();
+    let k = 0.01720209895;
+    println!("astro G: {} {}", astro.g(), k * k);
+    if f64::abs(astro.g() / (k * k) - 1.0) > 1e-4 || Units::system("nbody") != Some(None) {
+        println!("Error1"); exit(1);
+    }
+
+    // the unit of time of solar.txt, G = 2.92e-6 in AU and Earth masses
+    let solar = Units::with_g(AU, 5.9722e24, 2.92e-6);
+    println!("solar time unit: {} days", solar.time / DAY);
+    if f64::abs(solar.g() / 2.92e-6 - 1.0) > 1e-14 || f64::abs(solar.time / DAY - 57.3) > 0.1 {
+        println!("Error2"); exit(1);
+    }
+
+    if Units::by_name("au", "moon", "day").is_some() || Units::by_name("-1", "kg", "s").is_some()
+        || Units::by_name("1e3", "1", "60") != Some(Units { length : 1e3, mass : 1.0, time : 60.0 }) {
+        println!("Error3"); exit(1);
+    }
+    println!("Ok");
+}
@@ -0,0 +1,286 @@
+use crate::body::{Particles, System};
+use crate::gravity::{pairwise, add_external, add_positional, implicit_kick};
+use crate::integrator::Integrator;
+
+// Wisdom-Holman map in democratic heliocentric coordinates:
+// heliocentric positions, barycentric velocities. The dominant body is the
+// central mass, the motion around it is advanced with an analytic Kepler drift
+// and the mutual attraction of the other bodies is applied as kicks.
+pub struct WisdomHolman {
+    // index of the central body
+    central: usize,
+    // heliocentric positions and barycentric velocities, central entry unused
+    q: Vec<[f64; 3]>,
+    p: Vec<[f64; 3]>,
+    scratch: Particles
+}
+
+impl WisdomHolman {
+    pub fn new() -> WisdomHolman {
+        WisdomHolman {
+            central : 0,
+            q : Vec::new(),
+            p : Vec::new(),
+            scratch : Particles::new()
+        }
+    }
+
+    // mutual attraction of the non-central bodies
+    fn kick(&mut self, data: &System, h: f64) {
+        let c = self.central;
+        for i in 0..self.scratch.len() {
+            self.scratch.set_r(i, self.q[i]);
+        }
+        self.scratch.m[c] = 0.0;
+        pairwise(&mut self.scratch, data.g, &data.forces);
+        for i in 0..self.scratch.len() {
+            if i == c || data.particles.fixed[i] { continue; }
+            let a = self.scratch.a(i);
+            for (pk, ak) in self.p[i].iter_mut().zip(a) {
+                *pk += h * ak;
+            }
+        }
+    }
+
+    // external fields and springs act on all bodies in the original frame,
+    // they wrap the map as half kicks; the closing one is implicit in the
+    // velocity dependent forces to keep the map symmetric
+    fn external_kick(&mut self, data: &mut System, h: f64, last: bool) {
+        let f = &data.forces;
+        if f.constraints.is_empty() && f.fields.is_empty() && f.light_speed <= 0.0 && f.nongrav.is_empty() {
+            return;
+        }
+        let s = &mut self.scratch;
+        s.clone_from(&data.particles);
+        for i in 0..s.len() {
+            s.set_a(i, [0.0, 0.0, 0.0]);
+        }
+        if last {
+            add_positional(s, data.g, f);
+            implicit_kick(s, data.g, f, h);
+        } else {
+            add_external(s, data.g, f);
+            for i in 0..s.len() {
+                s.vx[i] += h * s.ax[i];
+                s.vy[i] += h * s.ay[i];
+                s.vz[i] += h * s.az[i];
+            }
+        }
+        let p = &mut data.particles;
+        for i in 0..p.len() {
+            if p.fixed[i] { continue; }
+            p.set_v(i, s.v(i));
+        }
+    }
+
+    // motion of the central body relative to the barycenter
+    fn jump(&mut self, data: &System, h: f64) {
+        let c = self.central;
+        let pt = &data.particles;
+        let mut ptot = [0.0, 0.0, 0.0];
+        for i in 0..pt.len() {
+            if i == c { continue; }
+            for (s, pk) in ptot.iter_mut().zip(self.p[i]) {
+                *s += pt.m[i] * pk;
+            }
+        }
+        for i in 0..pt.len() {
+            if i == c || pt.fixed[i] { continue; }
+            for (qk, s) in self.q[i].iter_mut().zip(ptot) {
+                *qk += h * s / pt.m[c];
+            }
+        }
+    }
+
+    // the central attraction stays a point mass, softening only enters the kicks
+    fn drift(&mut self, data: &System, h: f64) {
+        let c = self.central;
+        let pt = &data.particles;
+        let mu = data.g * pt.m[c];
+        for i in 0..pt.len() {
+            if i == c || pt.fixed[i] { continue; }
+            kepler_drift(mu, &mut self.q[i], &mut self.p[i], h);
+        }
+    }
+}
+
+impl Default for WisdomHolman {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Integrator for WisdomHolman {
+    fn name(&self) -> &'static str {
+        "wh"
+    }
+
+    fn order(&self) -> u32 {
+        2
+    }
+
+    fn kepler_exact(&self) -> bool {
+        true
+    }
+
+    fn init(&mut self, data: &mut System) {
+        let m = &data.particles.m;
+        let mut c = 0;
+        for i in 0..m.len() {
+            if m[i] > m[c] {
+                c = i;
+            }
+        }
+        self.central = c;
+        self.scratch.clone_from(&data.particles);
+    }
+
+    fn step(&mut self, data: &mut System) {
+        let n = data.particles.len();
+        let c = self.central;
+        let dt = data.dt;
+        if n < 2 {
+            return;
+        }
+        self.external_kick(data, 0.5 * dt, false);
+
+        // a fixed central body does not move, the frame is heliocentric and at rest
+        let pt = &data.particles;
+        let fixed = pt.fixed[c];
+        let mut mtot = 0.0;
+        let mut rcm = [0.0, 0.0, 0.0];
+        let mut vcm = [0.0, 0.0, 0.0];
+        for i in 0..n {
+            let (r, v) = (pt.r(i), pt.v(i));
+            mtot += pt.m[i];
+            for k in 0..3 {
+                rcm[k] += pt.m[i] * r[k];
+                vcm[k] += pt.m[i] * v[k];
+            }
+        }
+        for k in 0..3 {
+            rcm[k] /= mtot;
+            vcm[k] /= mtot;
+        }
+        if fixed {
+            rcm = pt.r(c);
+            vcm = [0.0, 0.0, 0.0];
+        }
+
+        self.q.resize(n, [0.0, 0.0, 0.0]);
+        self.p.resize(n, [0.0, 0.0, 0.0]);
+        let rc = pt.r(c);
+        for i in 0..n {
+            let (r, v) = (pt.r(i), pt.v(i));
+            for k in 0..3 {
+                self.q[i][k] = r[k] - rc[k];
+                self.p[i][k] = v[k] - vcm[k];
+            }
+        }
+        self.q[c] = [0.0, 0.0, 0.0];
+
+        self.kick(data, 0.5 * dt);
+        if !fixed { self.jump(data, 0.5 * dt); }
+        self.drift(data, dt);
+        if !fixed { self.jump(data, 0.5 * dt); }
+        self.kick(data, 0.5 * dt);
+
+        // back to the original frame
+        let pt = &mut data.particles;
+        let mc = pt.m[c];
+        let mut mq = [0.0, 0.0, 0.0];
+        let mut mp = [0.0, 0.0, 0.0];
+        for i in 0..n {
+            if i == c { continue; }
+            for k in 0..3 {
+                mq[k] += pt.m[i] * self.q[i][k];
+                mp[k] += pt.m[i] * self.p[i][k];
+            }
+        }
+        let mut rc = [0.0, 0.0, 0.0];
+        for k in 0..3 {
+            rc[k] = if fixed { rcm[k] + vcm[k] * dt } else { rcm[k] + vcm[k] * dt - mq[k] / mtot };
+        }
+        for i in 0..n {
+            if pt.fixed[i] && i != c { continue; }
+            let (mut r, mut v) = (pt.r(i), pt.v(i));
+            for k in 0..3 {
+                if i == c {
+                    r[k] = rc[k];
+                    if !fixed { v[k] = vcm[k] - mp[k] / mc; }
+                } else {
+                    r[k] = rc[k] + self.q[i][k];
+                    v[k] = vcm[k] + self.p[i][k];
+                }
+            }
+            pt.set_r(i, r);
+            pt.set_v(i, v);
+        }
+        self.external_kick(data, 0.5 * dt, true);
+    }
+}
+
+// Stumpff functions c2(z), c3(z)
+fn stumpff(z: f64) -> (f64, f64) {
+    if z.abs() < 1e-3 {
+        let c2 = 1.0 / 2.0 - z / 24.0 + z * z / 720.0 - z * z * z / 40320.0;
+        let c3 = 1.0 / 6.0 - z / 120.0 + z * z / 5040.0 - z * z * z / 362880.0;
+        (c2, c3)
+    } else if z > 0.0 {
+        let s = f64::sqrt(z);
+        ((1.0 - s.cos()) / z, (s - s.sin()) / (s * z))
+    } else {
+        let s = f64::sqrt(-z);
+        ((s.cosh() - 1.0) / -z, (s.sinh() - s) / (s * -z))
+    }
+}
+
+// advances a two-body orbit with gravitational parameter mu by h using
+// universal variables and Laguerre-Conway iterations for Kepler's equation,
+// works for elliptic, parabolic and hyperbolic orbits
+pub fn kepler_drift(mu: f64, r: &mut [f64; 3], v: &mut [f64; 3], h: f64) {
+    let r0 = f64::sqrt(r[0] * r[0] + r[1] * r[1] + r[2] * r[2]);
+    let v2 = v[0] * v[0] + v[1] * v[1] + v[2] * v[2];
+    if r0 == 0.0 || mu == 0.0 {
+        for k in 0..3 {
+            r[k] += h * v[k];
+        }
+        return;
+    }
+    let sqmu = f64::sqrt(mu);
+    let rv = (r[0] * v[0] + r[1] * v[1] + r[2] * v[2]) / sqmu;
+    // inverse semi-major axis
+    let alpha = 2.0 / r0 - v2 / mu;
+
+    let n = 5.0;
+    let mut x = sqmu * h / r0;
+    for _ in 0..50 {
+        let z = alpha * x * x;
+        let (c2, c3) = stumpff(z);
+        let f = rv * x * x * c2 + (1.0 - alpha * r0) * x * x * x * c3 + r0 * x - sqmu * h;
+        let df = rv * x * (1.0 - z * c3) + (1.0 - alpha * r0) * x * x * c2 + r0;
+        let ddf = rv * (1.0 - z * c2) + (1.0 - alpha * r0) * x * (1.0 - z * c3);
+        let disc = f64::sqrt(f64::abs((n - 1.0) * (n - 1.0) * df * df - n * (n - 1.0) * f * ddf));
+        let dx = n * f / (df + df.signum() * disc);
+        x -= dx;
+        if dx.abs() <= 1e-15 * x.abs().max(1e-300) {
+            break;
+        }
+    }
+
+    let z = alpha * x * x;
+    let (c2, c3) = stumpff(z);
+    let f = 1.0 - x * x / r0 * c2;
+    let g = h - x * x * x / sqmu * c3;
+    let mut rn = [0.0, 0.0, 0.0];
+    for k in 0..3 {
+        rn[k] = f * r[k] + g * v[k];
+    }
+    let rl = f64::sqrt(rn[0] * rn[0] + rn[1] * rn[1] + rn[2] * rn[2]);
+    let fdot = sqmu / (rl * r0) * (z * x * c3 - x);
+    let gdot = 1.0 - x * x / rl * c2;
+    for k in 0..3 {
+        v[k] = fdot * r[k] + gdot * v[k];
+        r[k] = rn[k];
+    }
+}